@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+use wind_lang::Source;
+
+const CHUNK: &str = r#"// greets everyone in the list
+fun greet(names) {
+    for name in names {
+        println("مرحبا " + name + ", ¡hola!");
+    }
+}
+
+var people = ["Ahmed", "Sara", "Zoë"];
+var scores = {"Ahmed": 10.5, "Sara": 12, "Zoë": 7};
+
+for i in 0..10 {
+    if scores["Sara"] >= i * 2 and i != 3 {
+        greet(people);
+    }
+}
+"#;
+
+fn script(size: usize) -> String {
+    CHUNK.repeat(size / CHUNK.len() + 1)
+}
+
+fn scan(code: &str) -> (usize, Duration) {
+    let source = Source::new("<bench>", code.to_owned());
+    let start = Instant::now();
+    let tokens = wind_lang::scan(source).unwrap();
+
+    (tokens.len(), start.elapsed())
+}
+
+// Scans scripts of growing size; with a linear scanner doubling the input
+// roughly doubles the time.
+fn main() {
+    let mut previous: Option<Duration> = None;
+
+    for megabytes in &[1, 2, 4, 8] {
+        let code = script(megabytes * 1024 * 1024);
+        let (tokens, elapsed) = scan(&code);
+        let throughput = code.len() as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64();
+
+        print!(
+            "scan {} MiB: {} tokens in {:?} ({:.1} MiB/s)",
+            megabytes, tokens, elapsed, throughput
+        );
+
+        if let Some(previous) = previous {
+            print!(
+                ", {:.2}x the previous size's time",
+                elapsed.as_secs_f64() / previous.as_secs_f64()
+            );
+        }
+
+        println!();
+        previous = Some(elapsed);
+    }
+}
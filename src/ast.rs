@@ -1,13 +1,28 @@
-use std::{fmt::Display, rc::Rc};
+use std::{
+    cell::{Cell, OnceCell},
+    fmt::Display,
+    rc::Rc,
+};
 
-use crate::{token::Token, types::LiteralType};
+use crate::{module::Module, token::Token, types::LiteralType};
 use enum_as_inner::EnumAsInner;
 
+// Where a local variable lives at runtime: how many environments up from the
+// current one, and its index inside that environment. Globals have no binding.
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub depth: usize,
+    pub slot: usize,
+}
+
 #[derive(EnumAsInner, Clone)]
 pub enum Expr {
     Group(Rc<Expr>),
     Literal(LiteralType),
-    Variable(Token),
+    Variable {
+        name: Token,
+        binding: Cell<Option<Binding>>,
+    },
     Binary {
         left: Rc<Expr>,
         operator: Token,
@@ -25,12 +40,58 @@ pub enum Expr {
     Assign {
         name: Token,
         value: Rc<Expr>,
+        binding: Cell<Option<Binding>>,
     },
     Logical {
         left: Rc<Expr>,
         operator: Token,
         right: Rc<Expr>,
     },
+    Get {
+        object: Rc<Expr>,
+        name: Token,
+    },
+    // A compound assignment like `a.b += c` keeps its operator here, so the
+    // object is only evaluated once. The same goes for `SetIndex`.
+    Set {
+        object: Rc<Expr>,
+        name: Token,
+        value: Rc<Expr>,
+        operator: Option<Token>,
+    },
+    List(Vec<Expr>),
+    Interpolation(Vec<Expr>),
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    Index {
+        object: Rc<Expr>,
+        bracket: Token,
+        index: Rc<Expr>,
+    },
+    SetIndex {
+        object: Rc<Expr>,
+        bracket: Token,
+        index: Rc<Expr>,
+        value: Rc<Expr>,
+        operator: Option<Token>,
+    },
+    Lambda(Rc<Function>),
+    This {
+        keyword: Token,
+        binding: Cell<Option<Binding>>,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        binding: Cell<Option<Binding>>,
+    },
+    Match {
+        keyword: Token,
+        value: Rc<Expr>,
+        arms: Vec<MatchArm>,
+    },
 }
 
 impl Display for Expr {
@@ -44,7 +105,7 @@ impl Display for Expr {
                     LiteralType::String(string) => format!("\"{}\"", string),
                     _ => format!("{}", literal),
                 },
-                Expr::Variable(name) => format!("{}", name.lexeme),
+                Expr::Variable { name, .. } => name.lexeme.to_string(),
                 Expr::Binary {
                     left,
                     operator,
@@ -68,17 +129,210 @@ impl Display for Expr {
 
                     format!("{}({})", callee, arg_str)
                 }
-                Expr::Assign { name, value } => format!("{} = {}", name.lexeme, value),
+                Expr::Assign { name, value, .. } => format!("{} = {}", name.lexeme, value),
                 Expr::Logical {
                     left,
                     operator,
                     right,
                 } => format!("{} {} {}", left, operator.lexeme, right),
+                Expr::Get { object, name } => format!("{}.{}", object, name.lexeme),
+                Expr::Set {
+                    object,
+                    name,
+                    value,
+                    operator,
+                } => format!(
+                    "{}.{} {} {}",
+                    object,
+                    name.lexeme,
+                    operator
+                        .as_ref()
+                        .map_or("=", |operator| operator.lexeme.as_str()),
+                    value
+                ),
+                Expr::List(items) => {
+                    let mut items_str = String::new();
+
+                    for (index, item) in items.iter().enumerate() {
+                        items_str += format!("{}", item).as_str();
+
+                        if index < items.len() - 1 {
+                            items_str += ", ";
+                        }
+                    }
+
+                    format!("[{}]", items_str)
+                }
+                Expr::Interpolation(parts) => {
+                    let mut parts_str = String::new();
+
+                    for part in parts {
+                        match part {
+                            Expr::Literal(LiteralType::String(string)) => parts_str += string,
+                            _ => parts_str += format!("${{{}}}", part).as_str(),
+                        }
+                    }
+
+                    format!("\"{}\"", parts_str)
+                }
+                Expr::Map { brace: _, entries } => {
+                    let mut entries_str = String::new();
+
+                    for (index, (key, value)) in entries.iter().enumerate() {
+                        entries_str += format!("{}: {}", key, value).as_str();
+
+                        if index < entries.len() - 1 {
+                            entries_str += ", ";
+                        }
+                    }
+
+                    format!("{{{}}}", entries_str)
+                }
+                Expr::Index {
+                    object,
+                    bracket: _,
+                    index,
+                } => format!("{}[{}]", object, index),
+                Expr::SetIndex {
+                    object,
+                    bracket: _,
+                    index,
+                    value,
+                    operator,
+                } => format!(
+                    "{}[{}] {} {}",
+                    object,
+                    index,
+                    operator
+                        .as_ref()
+                        .map_or("=", |operator| operator.lexeme.as_str()),
+                    value
+                ),
+                Expr::Lambda(function) => format!("{}", function),
+                Expr::This { .. } => "this".to_owned(),
+                Expr::Super { method, .. } => format!("super.{}", method.lexeme),
+                Expr::Match { value, arms, .. } => {
+                    let arms: Vec<String> = arms.iter().map(|arm| arm.to_string()).collect();
+
+                    format!("match {} {{{}}}", value, arms.join(", "))
+                }
             }
         )
     }
 }
 
+#[derive(Clone)]
+pub struct MatchArm {
+    pub pattern: Rc<Pattern>,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+impl Display for MatchArm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.guard {
+            Some(guard) => write!(f, "{} if {} => {}", self.pattern, guard, self.body),
+            None => write!(f, "{} => {}", self.pattern, self.body),
+        }
+    }
+}
+
+pub enum Pattern {
+    Wildcard,
+    Literal(LiteralType),
+    // Numbers from `start` to `end`, both included like in a `for` range.
+    Range(LiteralType, LiteralType),
+    Binding(Token),
+    List {
+        items: Vec<Pattern>,
+        // With `..rest`, whatever comes after the listed items is matched
+        // against it as a list of its own.
+        rest: Option<Box<Pattern>>,
+    },
+    // Maps holding at least these keys, with values matching their patterns.
+    Map(Vec<(LiteralType, Pattern)>),
+}
+
+impl Pattern {
+    // The names the pattern binds, in the order their values are bound.
+    pub fn bindings(&self) -> Vec<&Token> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range(..) => Vec::new(),
+            Pattern::Binding(name) => vec![name],
+            Pattern::List { items, rest } => items
+                .iter()
+                .chain(rest.as_deref())
+                .flat_map(Pattern::bindings)
+                .collect(),
+            Pattern::Map(entries) => entries
+                .iter()
+                .flat_map(|(_, pattern)| pattern.bindings())
+                .collect(),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(value) => write!(f, "{}", value.to_repr()),
+            Pattern::Range(start, end) => write!(f, "{}..{}", start, end),
+            Pattern::Binding(name) => write!(f, "{}", name.lexeme),
+            Pattern::List { items, rest } => {
+                let mut items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+
+                if let Some(rest) = rest {
+                    items.push(format!("..{}", rest));
+                }
+
+                write!(f, "[{}]", items.join(", "))
+            }
+            Pattern::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, pattern)| format!("{}: {}", key.to_repr(), pattern))
+                    .collect();
+
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+pub struct Function {
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut params_str = String::new();
+
+        for (index, param) in self.params.iter().enumerate() {
+            params_str += param.lexeme.to_string().as_str();
+
+            if index < self.params.len() - 1 {
+                params_str += ", ";
+            }
+        }
+
+        let mut body_str = String::new();
+
+        for stmt in &self.body {
+            body_str += format!("{}", stmt).as_str();
+        }
+
+        let name = match &self.name {
+            Some(name) => name.lexeme.as_str(),
+            None => "anonymous",
+        };
+
+        write!(f, "fun {} (params {}) {{{}}}", name, params_str, body_str)
+    }
+}
+
 #[derive(EnumAsInner, Clone)]
 pub enum Stmt {
     Expression(Rc<Expr>),
@@ -90,6 +344,7 @@ pub enum Stmt {
     While {
         condition: Option<Rc<Expr>>,
         body: Rc<Stmt>,
+        increment: Option<Rc<Expr>>,
     },
     ForRange {
         name: Token,
@@ -97,20 +352,51 @@ pub enum Stmt {
         range_end: Rc<Expr>,
         body: Rc<Stmt>,
     },
+    ForIn {
+        name: Token,
+        iterable: Rc<Expr>,
+        body: Rc<Stmt>,
+    },
     If {
         condition: Rc<Expr>,
         then_branch: Rc<Stmt>,
         else_branch: Option<Rc<Stmt>>,
     },
-    FunctionDecl {
+    FunctionDecl(Rc<Function>),
+    Class {
         name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        superclass: Option<Rc<Expr>>,
+        methods: Vec<Rc<Function>>,
     },
     Return {
         keyword: Token,
         value: Rc<Expr>,
     },
+    Break(Token),
+    Continue(Token),
+    Import {
+        keyword: Token,
+        path: Token,
+        // `import "path" as name` binds the whole module to `name`, while
+        // `import x, y from "path"` binds the listed exports instead.
+        name: Option<Token>,
+        names: Vec<Token>,
+        module: OnceCell<Rc<Module>>,
+    },
+    Export {
+        keyword: Token,
+        declaration: Rc<Stmt>,
+    },
+    Throw {
+        keyword: Token,
+        value: Rc<Expr>,
+    },
+    Try {
+        body: Rc<Stmt>,
+        // The name the caught error is bound to, and the block handling it.
+        catch: Option<(Token, Rc<Stmt>)>,
+        finally: Option<Rc<Stmt>>,
+    },
 }
 
 impl Display for Stmt {
@@ -137,13 +423,25 @@ impl Display for Stmt {
 
                     format!("var {} = {}", name.lexeme, initializer_str)
                 }
-                Stmt::While { condition, body } => {
+                Stmt::While {
+                    condition,
+                    body,
+                    increment,
+                } => {
                     let condition_str = match condition {
                         Some(expr) => format!("{}", expr),
                         None => "None".to_owned(),
                     };
 
-                    format!("while (condition {}) {}", condition_str, body)
+                    let increment_str = match increment {
+                        Some(expr) => format!("{}", expr),
+                        None => "None".to_owned(),
+                    };
+
+                    format!(
+                        "while (condition {}) (increment {}) {}",
+                        condition_str, increment_str, body
+                    )
                 }
                 Stmt::ForRange {
                     name,
@@ -152,11 +450,13 @@ impl Display for Stmt {
                     body,
                 } => format!(
                     "for {} range (start {}) (end {}) {}",
-                    name.lexeme,
-                    format!("{}", range_start),
-                    format!("{}", range_end),
-                    format!("{}", body)
+                    name.lexeme, range_start, range_end, body
                 ),
+                Stmt::ForIn {
+                    name,
+                    iterable,
+                    body,
+                } => format!("for {} in {} {}", name.lexeme, iterable, body),
                 Stmt::If {
                     condition,
                     then_branch,
@@ -169,36 +469,66 @@ impl Display for Stmt {
 
                     format!(
                         "if (condition {}) (then {}) (else {})",
-                        format!("{}", condition),
-                        format!("{}", then_branch),
-                        format!("{}", else_str)
+                        condition, then_branch, else_str
                     )
                 }
-                Stmt::FunctionDecl { name, params, body } => {
-                    let mut params_str = String::new();
+                Stmt::FunctionDecl(function) => format!("{}", function),
+                Stmt::Class {
+                    name,
+                    superclass,
+                    methods,
+                } => {
+                    let superclass_str = match superclass {
+                        Some(expr) => format!("{}", expr),
+                        None => "None".to_owned(),
+                    };
 
-                    for (index, param) in params.iter().enumerate() {
-                        params_str += format!("{}", param.lexeme).as_str();
+                    let mut methods_str = String::new();
 
-                        if index < params.len() - 1 {
-                            params_str += ", ";
-                        }
+                    for method in methods {
+                        methods_str += format!("{}", method).as_str();
                     }
 
-                    let mut body_str = String::new();
+                    format!(
+                        "class {} (super {}) {{{}}}",
+                        name.lexeme, superclass_str, methods_str
+                    )
+                }
+                Stmt::Return { keyword: _, value } => format!("return {}", value),
+                Stmt::Break(keyword) | Stmt::Continue(keyword) => keyword.lexeme.to_owned(),
+                Stmt::Import {
+                    path, name, names, ..
+                } => match name {
+                    Some(name) => format!("import {} as {}", path.lexeme, name.lexeme),
+                    None => {
+                        let names: Vec<&str> =
+                            names.iter().map(|name| name.lexeme.as_str()).collect();
 
-                    for stmt in body {
-                        body_str += format!("{}", stmt).as_str();
+                        format!("import {} from {}", names.join(", "), path.lexeme)
                     }
+                },
+                Stmt::Export { declaration, .. } => format!("export{}", declaration),
+                Stmt::Throw { keyword: _, value } => format!("throw {}", value),
+                Stmt::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    let catch_str = match catch {
+                        Some((name, handler)) => format!("{} {}", name.lexeme, handler),
+                        None => "None".to_owned(),
+                    };
+
+                    let finally_str = match finally {
+                        Some(stmt) => format!("{}", stmt),
+                        None => "None".to_owned(),
+                    };
 
                     format!(
-                        "fun {} (params {}) {}",
-                        name.lexeme,
-                        params_str,
-                        format!("{{{}}}", body_str)
+                        "try {} (catch {}) (finally {})",
+                        body, catch_str, finally_str
                     )
                 }
-                Stmt::Return { keyword: _, value } => format!("return {}", format!("{}", value)),
             }
         )
     }
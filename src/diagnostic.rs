@@ -0,0 +1,163 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::token::Span;
+
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+pub struct Diagnostic {
+    pub(crate) level: Level,
+    pub(crate) message: String,
+    pub(crate) labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, span: &Span) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            message: message.to_owned(),
+            labels: vec![Label {
+                span: span.to_owned(),
+                message: String::new(),
+                primary: true,
+            }],
+        }
+    }
+
+    pub fn warning(message: &str, span: &Span) -> Diagnostic {
+        Diagnostic {
+            level: Level::Warning,
+            ..Diagnostic::new(message, span)
+        }
+    }
+
+    pub fn with_label(mut self, span: &Span, message: &str) -> Diagnostic {
+        self.labels.push(Label {
+            span: span.to_owned(),
+            message: message.to_owned(),
+            primary: false,
+        });
+
+        self
+    }
+}
+
+// Renders something like:
+//
+// error: expected 2 arguments but got 1
+//  --> examples/functions.wind:5:14
+//   |
+// 1 | fun greet(name, greeting) {
+//   |     ----- function declared here
+//   |
+// 5 | println(greet("Ahmed"));
+//   |                      ^
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+
+        write!(f, "{}: {}", level, self.message)?;
+
+        let primary = match self.labels.iter().find(|label| label.primary) {
+            Some(primary) => primary,
+            None => return Ok(()),
+        };
+
+        let gutter = self
+            .labels
+            .iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        // Labels from the primary file come first, then everything is in source order.
+        labels.sort_by_key(|label| {
+            (
+                !Rc::ptr_eq(&label.span.source, &primary.span.source),
+                label.span.line,
+                label.span.column,
+            )
+        });
+
+        let mut previous: Option<&Label> = None;
+        for label in labels {
+            let same_source = previous
+                .map(|previous| Rc::ptr_eq(&previous.span.source, &label.span.source))
+                .unwrap_or(false);
+            let same_line = same_source && previous.unwrap().span.line == label.span.line;
+
+            if !same_source {
+                let location = if Rc::ptr_eq(&label.span.source, &primary.span.source) {
+                    primary
+                } else {
+                    label
+                };
+
+                write!(
+                    f,
+                    "\n{}{} ",
+                    pad,
+                    if previous.is_none() { "-->" } else { ":::" }
+                )?;
+                write!(
+                    f,
+                    "{}:{}:{}",
+                    location.span.source.name, location.span.line, location.span.column
+                )?;
+            }
+
+            let code = &label.span.source.code;
+            let line_text = code
+                .lines()
+                .nth((label.span.line - 1) as usize)
+                .unwrap_or("");
+
+            if !same_line {
+                write!(f, "\n{} |", pad)?;
+                write!(
+                    f,
+                    "\n{:>width$} | {}",
+                    label.span.line,
+                    line_text,
+                    width = gutter
+                )?;
+            }
+
+            // Reuse the tabs from the source line so the markers line up with it.
+            let indent: String = line_text
+                .chars()
+                .take(label.span.column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            let text = code.get(label.span.start..label.span.end).unwrap_or("");
+            let width = text.lines().next().unwrap_or("").chars().count().max(1);
+            let marker = if label.primary { "^" } else { "-" };
+
+            let mut marker_line = format!("{}{}", indent, marker.repeat(width));
+            if !label.message.is_empty() {
+                marker_line += format!(" {}", label.message).as_str();
+            }
+
+            write!(f, "\n{} | {}", pad, marker_line)?;
+
+            previous = Some(label);
+        }
+
+        Ok(())
+    }
+}
@@ -1,32 +1,247 @@
-use std::process;
+use std::{cell::RefCell, fmt::Display, io, rc::Rc};
 
-use crate::token::Token;
+use fnv::FnvHashMap;
 
-pub trait WindError {
-    fn report(&self);
+use crate::{
+    diagnostic::Diagnostic,
+    interpreter::CallFrame,
+    token::{Span, Token, TokenType},
+    types::{Class, Instance, LiteralType},
+};
+
+pub trait WindError: Display {
+    fn report(&self) {
+        eprintln!("{}", self);
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Scanner(Vec<ScannerError>),
+    Parse(Vec<ParseError>),
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 66,
+            Error::Scanner(_) => 65,
+            Error::Parse(_) | Error::Runtime(_) => 1,
+        }
+    }
+
+    pub fn is_incomplete_input(&self) -> bool {
+        match self {
+            Error::Parse(errors) => errors.iter().any(|e| e.is_at_end()),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Scanner(errors) => write_all(f, errors),
+            Error::Parse(errors) => write_all(f, errors),
+            Error::Runtime(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+fn write_all<T: Display>(f: &mut std::fmt::Formatter<'_>, errors: &[T]) -> std::fmt::Result {
+    for (index, error) in errors.iter().enumerate() {
+        if index > 0 {
+            write!(f, "\n\n")?;
+        }
+
+        write!(f, "{}", error)?;
+    }
+
+    Ok(())
 }
 
+impl std::error::Error for Error {}
+
+impl WindError for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<Vec<ScannerError>> for Error {
+    fn from(errors: Vec<ScannerError>) -> Error {
+        Error::Scanner(errors)
+    }
+}
+
+impl From<Vec<ParseError>> for Error {
+    fn from(errors: Vec<ParseError>) -> Error {
+        Error::Parse(errors)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(error: RuntimeError) -> Error {
+        Error::Runtime(error)
+    }
+}
+
+// Calls nested deeper than this raise a "stack overflow" runtime error.
+pub(crate) const MAX_FRAMES: usize = 10_000;
+
+// How much native stack a call needs left before it runs, and how much more
+// to allocate when there isn't that much.
+pub(crate) const STACK_RED_ZONE: usize = 512 * 1024;
+pub(crate) const STACK_GROWTH: usize = 8 * 1024 * 1024;
+
+#[derive(Debug)]
 pub struct RuntimeError {
-    token: Token,
+    token: Box<Token>,
     message: String,
+    labels: Vec<(Span, String)>,
+    backtrace: Option<Vec<CallFrame>>,
+    // Set when the error comes from a `throw` rather than from the language.
+    thrown: Option<Box<LiteralType>>,
 }
 
 impl RuntimeError {
     pub fn new(token: Token, message: String) -> RuntimeError {
-        RuntimeError { token, message }
+        RuntimeError {
+            token: Box::new(token),
+            message,
+            labels: Vec::new(),
+            backtrace: None,
+            thrown: None,
+        }
     }
-}
 
-impl WindError for RuntimeError {
-    fn report(&self) {
-        eprintln!(
-            "[line {}]: near '{}' {}",
-            self.token.line, self.token.lexeme, self.message
+    /// The error `throw` raises. If nothing catches it, it's reported with the
+    /// value's `message` field when it has one, so rethrowing a caught error
+    /// reads like the original.
+    pub fn thrown(token: Token, value: LiteralType) -> RuntimeError {
+        let message = match &value {
+            LiteralType::Instance(instance) => match instance.borrow().fields.get("message") {
+                Some(LiteralType::String(message)) => Some(message.to_owned()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let message = message.unwrap_or_else(|| format!("uncaught exception: {}", value.to_repr()));
+
+        RuntimeError {
+            thrown: Some(Box::new(value)),
+            ..RuntimeError::new(token, message)
+        }
+    }
+
+    /// What a `catch` clause gets: the value that was thrown, or an `Error`
+    /// instance with the `message`, `line` and `stack` of any other error.
+    pub fn to_value(&self) -> LiteralType {
+        if let Some(value) = &self.thrown {
+            return value.as_ref().to_owned();
+        }
+
+        let stack = self
+            .backtrace
+            .iter()
+            .flatten()
+            .map(|frame| LiteralType::String(frame.to_string()))
+            .collect::<Vec<LiteralType>>();
+
+        let mut fields = FnvHashMap::default();
+        fields.insert(
+            "message".to_owned(),
+            LiteralType::from(self.message.as_str()),
         );
-        process::exit(1);
+        fields.insert("line".to_owned(), LiteralType::from(self.token.span.line));
+        fields.insert("stack".to_owned(), LiteralType::from(stack));
+
+        LiteralType::Instance(Rc::new(RefCell::new(Instance {
+            class: Rc::new(Class {
+                name: "Error".to_owned(),
+                superclass: None,
+                methods: FnvHashMap::default(),
+            }),
+            fields,
+        })))
+    }
+
+    pub fn with_backtrace(mut self, frames: &[CallFrame]) -> RuntimeError {
+        if self.backtrace.is_none() {
+            self.backtrace = Some(frames.iter().rev().cloned().collect());
+        }
+
+        self
+    }
+
+    pub fn with_label(mut self, span: &Span, message: &str) -> RuntimeError {
+        self.labels.push((span.to_owned(), message.to_owned()));
+        self
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(&self.message, &self.token.span);
+
+        for (span, message) in &self.labels {
+            diagnostic = diagnostic.with_label(span, message);
+        }
+
+        diagnostic
     }
 }
 
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.diagnostic())?;
+
+        if let Some(frames) = &self.backtrace {
+            write!(f, "\n\nstack backtrace:")?;
+
+            let mut index = 0;
+
+            while index < frames.len() {
+                let frame = &frames[index];
+                let span = &frame.call_site.span;
+
+                write!(f, "\n{:>4}: {}", index, frame)?;
+
+                // Deep recursion would otherwise print the same line thousands of times.
+                let repeats = frames[index + 1..]
+                    .iter()
+                    .take_while(|other| {
+                        other.name == frame.name
+                            && other.native == frame.native
+                            && other.call_site.span.start == span.start
+                            && Rc::ptr_eq(&other.call_site.span.source, &span.source)
+                    })
+                    .count();
+
+                if repeats > 1 {
+                    write!(
+                        f,
+                        "\n      [previous frame repeated {} more times]",
+                        repeats
+                    )?;
+                    index += repeats;
+                }
+
+                index += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WindError for RuntimeError {}
+
+#[derive(Debug)]
 pub struct ParseError {
     token: Token,
     message: String,
@@ -36,29 +251,36 @@ impl ParseError {
     pub fn new(token: Token, message: String) -> ParseError {
         ParseError { token, message }
     }
+
+    pub fn is_at_end(&self) -> bool {
+        self.token.t_type == TokenType::EOF
+    }
 }
 
-impl WindError for ParseError {
-    fn report(&self) {
-        eprintln!("[line {}]: {}", self.token.line, self.message);
-        process::exit(1);
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Diagnostic::new(&self.message, &self.token.span))
     }
 }
 
+impl WindError for ParseError {}
+
+#[derive(Debug)]
 pub struct ScannerError {
-    line: i32,
+    span: Span,
     message: String,
 }
 
 impl ScannerError {
-    pub fn new(line: i32, message: String) -> ScannerError {
-        ScannerError { line, message }
+    pub fn new(span: Span, message: String) -> ScannerError {
+        ScannerError { span, message }
     }
 }
 
-impl WindError for ScannerError {
-    fn report(&self) {
-        eprintln!("[line {}]: {}", self.line, self.message);
-        process::exit(65);
+impl Display for ScannerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Diagnostic::new(&self.message, &self.span))
     }
 }
+
+impl WindError for ScannerError {}
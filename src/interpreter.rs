@@ -1,51 +1,195 @@
 pub(crate) mod environment;
-mod stdlib;
+pub(crate) mod stdlib;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::fmt::Display;
+use std::path::Path;
 use std::rc::Rc;
 
-use crate::error::{RuntimeError, WindError};
+use crate::error::{Error, RuntimeError, MAX_FRAMES, STACK_GROWTH, STACK_RED_ZONE};
 use crate::{
-    ast::{Expr, Stmt},
+    ast::{Binding, Expr, Stmt},
+    module::{self, Module, Modules},
+    operators,
     token::{Token, TokenType},
-    types::LiteralType,
+    types::{Class, Instance, LiteralType, Map, ModuleGlobals, Namespace},
+    Program,
 };
+use fnv::FnvHashMap;
 
 use self::environment::Environment;
-use self::stdlib::{Input, InputPrompt, Int, Print, PrintLn, StdLibFunc, Str};
+
+pub enum Flow {
+    Return(LiteralType),
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    pub name: String,
+    pub native: bool,
+    pub call_site: Token,
+}
+
+impl CallFrame {
+    fn new(callee: &LiteralType, call_site: &Token) -> CallFrame {
+        let name = match callee {
+            LiteralType::Function { deceleration, .. } => match &deceleration.name {
+                Some(name) => name.lexeme.to_owned(),
+                None => "anonymous".to_owned(),
+            },
+            LiteralType::NativeFunction { name, .. } => name.to_owned(),
+            LiteralType::Class(class) => class.name.to_owned(),
+            _ => callee.to_string(),
+        };
+
+        CallFrame {
+            name,
+            native: matches!(callee, LiteralType::NativeFunction { .. }),
+            call_site: call_site.to_owned(),
+        }
+    }
+}
+
+impl Display for CallFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let span = &self.call_site.span;
+
+        write!(
+            f,
+            "{}{} called at {}:{}:{}",
+            self.name,
+            if self.native { " [native]" } else { "" },
+            span.source.name,
+            span.line,
+            span.column
+        )
+    }
+}
 
 pub struct Interpreter {
-    pub environment: Rc<RefCell<Environment>>,
+    pub(crate) environment: Rc<RefCell<Environment>>,
+    globals: Rc<RefCell<Environment>>,
+    frames: Vec<CallFrame>,
+    modules: Modules,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
-        let mut global_env = Environment::new();
-
-        global_env.define(Input::name(), Input::function());
-        global_env.define(InputPrompt::name(), InputPrompt::function());
-        global_env.define(PrintLn::name(), PrintLn::function());
-        global_env.define(Print::name(), Print::function());
-        global_env.define(Int::name(), Int::function());
-        global_env.define(Str::name(), Str::function());
+        let globals = global_environment();
 
         Interpreter {
-            environment: Rc::new(RefCell::new(global_env)),
+            environment: globals.clone(),
+            globals,
+            frames: Vec::new(),
+            modules: FnvHashMap::default(),
         }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) {
-        for statement in &statements {
-            match self.execute(statement) {
-                Err(e) => {
-                    e.report();
-                }
-                _ => {}
+    /// Runs a whole program, keeping any globals it defines for later calls.
+    pub fn run(&mut self, source: &str) -> Result<(), Error> {
+        let program = crate::parse(source)?;
+        self.interpret(&program)?;
+
+        Ok(())
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let program = crate::parse_file(path)?;
+        self.interpret(&program)?;
+
+        Ok(())
+    }
+
+    /// Runs `source` and returns the value of its last statement when that
+    /// statement is a bare expression, or `nil` otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<LiteralType, Error> {
+        let program = crate::parse(source)?;
+        let value = self.interpret_repl(&program)?;
+
+        Ok(value.unwrap_or(LiteralType::Nil))
+    }
+
+    pub fn set_global<T: Into<LiteralType>>(&mut self, name: &str, value: T) {
+        self.globals
+            .borrow_mut()
+            .define(name.to_owned(), value.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LiteralType> {
+        self.globals.borrow().values.get(name).cloned()
+    }
+
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Vec<LiteralType>, Token) -> Result<LiteralType, RuntimeError> + 'static,
+    {
+        self.set_global(
+            name,
+            LiteralType::NativeFunction {
+                name: name.to_owned(),
+                arity,
+                func: Rc::new(func),
+            },
+        );
+    }
+
+    pub fn interpret(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        for statement in &program.statements {
+            self.execute_top_level(statement)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `interpret`, but gives back the value of the last statement when
+    /// it is a bare expression, for the REPL to show.
+    pub fn interpret_repl(
+        &mut self,
+        program: &Program,
+    ) -> Result<Option<LiteralType>, RuntimeError> {
+        let (last, rest) = match program.statements.split_last() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+
+        for statement in rest {
+            self.execute_top_level(statement)?;
+        }
+
+        match last {
+            Stmt::Expression(expr) => Ok(Some(self.evaluate(expr).map_err(|e| self.unwind(e))?)),
+            _ => {
+                self.execute_top_level(last)?;
+
+                Ok(None)
             }
         }
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<Option<LiteralType>, RuntimeError> {
+    fn execute_top_level(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
+        self.execute(statement).map_err(|e| self.unwind(e))?;
+
+        Ok(())
+    }
+
+    // A runtime error can leave `environment` pointing at whatever scope was
+    // active when it was raised, so go back to the globals before continuing.
+    fn unwind(&mut self, error: RuntimeError) -> RuntimeError {
+        self.environment = self.globals.clone();
+        self.frames.clear();
+
+        error
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Option<Flow>, RuntimeError> {
         match stmt {
             Stmt::Expression(expr) => {
                 self.evaluate(expr)?;
@@ -69,14 +213,23 @@ impl Interpreter {
 
                 Ok(None)
             }
-            Stmt::While { condition, body } => {
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
                 if let Some(condition) = condition {
                     let mut condition_value = self.evaluate(condition)?;
 
-                    while self.is_truthy(&condition_value) {
+                    while operators::is_truthy(&condition_value) {
                         match self.execute(body)? {
-                            Some(value) => return Ok(Some(value)),
-                            None => (),
+                            Some(Flow::Break) => break,
+                            Some(Flow::Return(value)) => return Ok(Some(Flow::Return(value))),
+                            Some(Flow::Continue) | None => {}
+                        }
+
+                        if let Some(increment) = increment {
+                            self.evaluate(increment)?;
                         }
 
                         condition_value = self.evaluate(condition)?;
@@ -95,27 +248,28 @@ impl Interpreter {
                 let end = self.evaluate(range_end)?;
 
                 match (start, end) {
-                    (LiteralType::Number(start_value), LiteralType::Number(end_value)) => {
+                    (LiteralType::Int(start_value), LiteralType::Int(end_value)) => {
                         let prev = self.environment.clone();
 
                         self.environment = Environment::with_enclosing(self.environment.clone());
 
                         self.environment
                             .borrow_mut()
-                            .define(name.lexeme.to_owned(), LiteralType::Number(start_value));
+                            .define(name.lexeme.to_owned(), LiteralType::Int(start_value));
 
-                        for i in start_value as i32..(end_value + 1.0) as i32 {
+                        for i in start_value..=end_value {
                             self.environment
                                 .borrow_mut()
-                                .assign(name.to_owned(), LiteralType::Number(i as f32))?;
+                                .assign_at(0, 0, LiteralType::Int(i));
 
-                            match self.execute(&(*body))? {
-                                Some(value) => {
+                            match self.execute(body)? {
+                                Some(Flow::Break) => break,
+                                Some(Flow::Return(value)) => {
                                     self.environment = prev;
 
-                                    return Ok(Some(value));
+                                    return Ok(Some(Flow::Return(value)));
                                 }
-                                _ => {}
+                                Some(Flow::Continue) | None => {}
                             }
                         }
 
@@ -124,10 +278,43 @@ impl Interpreter {
                     }
                     _ => Err(RuntimeError::new(
                         name.to_owned(),
-                        "range must be a number".to_owned(),
+                        "range must be an int".to_owned(),
                     )),
                 }
             }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                let iterable = self.evaluate(iterable)?;
+                let items = operators::iterate(name, iterable)?;
+
+                let prev = self.environment.clone();
+
+                self.environment = Environment::with_enclosing(self.environment.clone());
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.to_owned(), LiteralType::Nil);
+
+                for item in items {
+                    self.environment.borrow_mut().assign_at(0, 0, item);
+
+                    match self.execute(body)? {
+                        Some(Flow::Break) => break,
+                        Some(Flow::Return(value)) => {
+                            self.environment = prev;
+
+                            return Ok(Some(Flow::Return(value)));
+                        }
+                        Some(Flow::Continue) | None => {}
+                    }
+                }
+
+                self.environment = prev;
+                Ok(None)
+            }
             Stmt::If {
                 condition,
                 then_branch,
@@ -135,21 +322,20 @@ impl Interpreter {
             } => {
                 let if_condition = self.evaluate(condition)?;
 
-                if self.is_truthy(&if_condition) {
-                    return Ok(self.execute(&(*then_branch))?);
+                if operators::is_truthy(&if_condition) {
+                    return self.execute(then_branch);
                 } else if let Some(else_branch) = else_branch {
-                    return Ok(self.execute(&(*else_branch))?);
+                    return self.execute(else_branch);
                 }
 
                 Ok(None)
             }
-            Stmt::FunctionDecl {
-                name,
-                params: _,
-                body: _,
-            } => {
+            Stmt::FunctionDecl(function) => {
+                let name = function.name.as_ref().unwrap();
                 let function = LiteralType::Function {
-                    deceleration: stmt.to_owned(),
+                    deceleration: function.clone(),
+                    closure: self.environment.clone(),
+                    is_initializer: false,
                 };
 
                 self.environment
@@ -158,31 +344,175 @@ impl Interpreter {
 
                 Ok(None)
             }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(superclass_expr) => match self.evaluate(superclass_expr)? {
+                        LiteralType::Class(class) => Some(class),
+                        _ => {
+                            return Err(RuntimeError::new(
+                                name.to_owned(),
+                                "superclass must be a class".to_owned(),
+                            ))
+                        }
+                    },
+                    None => None,
+                };
+
+                let mut closure = self.environment.clone();
+                if let Some(superclass) = &superclass {
+                    closure = Environment::with_enclosing(closure);
+                    closure
+                        .borrow_mut()
+                        .define("super".to_owned(), LiteralType::Class(superclass.clone()));
+                }
+
+                let mut class_methods: FnvHashMap<String, LiteralType> = FnvHashMap::default();
+                for method in methods {
+                    let method_name = method.name.as_ref().unwrap();
+
+                    class_methods.insert(
+                        method_name.lexeme.to_owned(),
+                        LiteralType::Function {
+                            deceleration: method.clone(),
+                            closure: closure.clone(),
+                            is_initializer: method_name.lexeme == "init",
+                        },
+                    );
+                }
+
+                let class = LiteralType::Class(Rc::new(Class {
+                    name: name.lexeme.to_owned(),
+                    superclass,
+                    methods: class_methods,
+                }));
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.to_owned(), class);
+
+                Ok(None)
+            }
             Stmt::Return { keyword: _, value } => {
                 let value = self.evaluate(value)?;
 
-                Ok(Some(value))
+                Ok(Some(Flow::Return(value)))
+            }
+            Stmt::Break(_) => Ok(Some(Flow::Break)),
+            Stmt::Continue(_) => Ok(Some(Flow::Continue)),
+            Stmt::Import {
+                name,
+                names,
+                module,
+                ..
+            } => {
+                // The loader fills in every import before the program runs.
+                let namespace = self.import(module.get().unwrap())?;
+
+                match name {
+                    Some(name) => self
+                        .environment
+                        .borrow_mut()
+                        .define(name.lexeme.to_owned(), LiteralType::Module(namespace)),
+                    None => {
+                        for name in names {
+                            let value = namespace.get(name)?;
+
+                            self.environment
+                                .borrow_mut()
+                                .define(name.lexeme.to_owned(), value);
+                        }
+                    }
+                }
+
+                Ok(None)
+            }
+            Stmt::Export { declaration, .. } => self.execute(declaration),
+            Stmt::Throw { keyword, value } => {
+                let value = self.evaluate(value)?;
+
+                Err(RuntimeError::thrown(keyword.to_owned(), value))
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                // An error can leave `environment` pointing anywhere inside the
+                // try block, so every clause starts over from here.
+                let environment = self.environment.clone();
+                let mut result = self.execute(body);
+
+                if let Some((name, handler)) = catch {
+                    if let Err(error) = result {
+                        // An error raised in this very call has no backtrace yet.
+                        let error = if self.frames.is_empty() {
+                            error
+                        } else {
+                            error.with_backtrace(&self.frames)
+                        };
+
+                        self.environment = Environment::with_enclosing(environment.clone());
+                        self.environment
+                            .borrow_mut()
+                            .define(name.lexeme.to_owned(), error.to_value());
+
+                        result = self.execute(handler);
+                    }
+                }
+
+                self.environment = environment;
+
+                if let Some(finally) = finally {
+                    if let Some(flow) = self.execute(finally)? {
+                        return Ok(Some(flow));
+                    }
+                }
+
+                result
             }
         }
     }
 
-    pub fn execute_block(
+    fn import(&mut self, module: &Rc<Module>) -> Result<Rc<Namespace>, RuntimeError> {
+        module::import(
+            self,
+            |interpreter| &mut interpreter.modules,
+            module,
+            |interpreter| {
+                let globals = global_environment();
+                let previous = std::mem::replace(&mut interpreter.environment, globals.clone());
+
+                let result = module
+                    .statements
+                    .iter()
+                    .try_for_each(|statement| interpreter.execute(statement).map(|_| ()));
+
+                interpreter.environment = previous;
+                result?;
+
+                Ok(ModuleGlobals::Environment(globals))
+            },
+        )
+    }
+
+    pub(crate) fn execute_block(
         &mut self,
         statements: &Vec<Stmt>,
         environment: Rc<RefCell<Environment>>,
-    ) -> Result<Option<LiteralType>, RuntimeError> {
+    ) -> Result<Option<Flow>, RuntimeError> {
         let previous = self.environment.clone();
 
         self.environment = environment;
 
         for statement in statements {
-            match self.execute(statement)? {
-                Some(value) => {
-                    self.environment = previous;
+            if let Some(flow) = self.execute(statement)? {
+                self.environment = previous;
 
-                    return Ok(Some(value));
-                }
-                _ => (),
+                return Ok(Some(flow));
             };
         }
 
@@ -195,7 +525,7 @@ impl Interpreter {
         match expr {
             Expr::Group(expr) => self.evaluate(expr),
             Expr::Literal(value) => Ok(value.to_owned()),
-            Expr::Variable(name) => Ok(self.environment.borrow().get(&name)?),
+            Expr::Variable { name, binding } => self.look_up_variable(name, binding),
             Expr::Binary {
                 left,
                 operator,
@@ -204,123 +534,12 @@ impl Interpreter {
                 let left = self.evaluate(left)?;
                 let right = self.evaluate(right)?;
 
-                match operator.t_type {
-                    TokenType::Minus | TokenType::MinusEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Number(left_value - right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::Slash | TokenType::SlashEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Number(left_value / right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::Star | TokenType::StarEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Number(left_value * right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::Percent | TokenType::PercentEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Number(left_value % right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::Greater => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Bool(left_value > right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::GreaterEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Bool(left_value >= right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::Less => {
-                        self.check_number_operands(&operator, &left, &right)?;
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Bool(left_value < right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::LessEqual => {
-                        self.check_number_operands(&operator, &left, &right)?;
-
-                        match (left, right) {
-                            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                                Ok(LiteralType::Bool(left_value <= right_value))
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    TokenType::EqualEqual => {
-                        Ok(LiteralType::Bool(self.is_equal(&operator, left, right)?))
-                    }
-                    TokenType::BangEqual => {
-                        Ok(LiteralType::Bool(!self.is_equal(&operator, left, right)?))
-                    }
-                    TokenType::Plus | TokenType::PlusEqual => match (left, right) {
-                        (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                            Ok(LiteralType::Number(left_value + right_value))
-                        }
-                        (LiteralType::String(left_value), LiteralType::String(right_value)) => {
-                            let res = [left_value.to_owned(), right_value.to_owned()].join("");
-                            Ok(LiteralType::String(res))
-                        }
-                        _ => Err(RuntimeError::new(
-                            operator.to_owned(),
-                            "cannot add".to_owned(),
-                        )),
-                    },
-
-                    _ => unreachable!(),
-                }
+                operators::binary(operator, left, right)
             }
             Expr::Unary { operator, right } => {
                 let right = self.evaluate(right)?;
 
-                match operator.t_type {
-                    TokenType::Bang => Ok(LiteralType::Bool(self.is_truthy(&right))),
-                    TokenType::Minus => {
-                        self.check_number_operand(&operator, &right)?;
-                        match right {
-                            LiteralType::Number(value) => Ok(LiteralType::Number(-value)),
-                            _ => unreachable!(),
-                        }
-                    }
-                    _ => unreachable!(),
-                }
+                operators::unary(operator, right)
             }
             Expr::Call {
                 callee,
@@ -334,23 +553,49 @@ impl Interpreter {
                     args.push(self.evaluate(argument)?);
                 }
 
-                let arity = callee.arity(&paren)?;
+                callee.check_arity(paren, args.len())?;
 
-                if args.len() == arity {
-                    callee.call(self, &paren, args)
-                } else {
-                    Err(RuntimeError::new(
-                        paren.to_owned(),
-                        format!("expected {} arguments but got {}", arity, args.len()),
-                    ))
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(
+                        RuntimeError::new(paren.to_owned(), "stack overflow".to_owned())
+                            .with_backtrace(&self.frames),
+                    );
                 }
+
+                self.frames.push(CallFrame::new(&callee, paren));
+
+                // Every call recurses on the native stack, which is grown as
+                // needed so MAX_FRAMES is reached before the host's stack runs
+                // out, whatever thread it's running on.
+                let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || {
+                    callee.call(self, paren, args)
+                });
+
+                // The innermost call that sees the error records the stack as it was when it was raised.
+                let result = result.map_err(|e| e.with_backtrace(&self.frames));
+
+                self.frames.pop();
+
+                result
             }
-            Expr::Assign { name, value } => {
+            Expr::Assign {
+                name,
+                value,
+                binding,
+            } => {
                 let value = self.evaluate(value)?;
 
-                self.environment
-                    .borrow_mut()
-                    .assign(name.to_owned(), value.to_owned())?;
+                match binding.get() {
+                    Some(Binding { depth, slot }) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(depth, slot, value.to_owned())
+                    }
+                    None => self
+                        .environment
+                        .borrow_mut()
+                        .assign(name.to_owned(), value.to_owned())?,
+                }
 
                 Ok(value.to_owned())
             }
@@ -362,73 +607,222 @@ impl Interpreter {
                 let left = self.evaluate(left)?;
 
                 if operator.t_type == TokenType::Or {
-                    if self.is_truthy(&left) {
+                    if operators::is_truthy(&left) {
                         return Ok(left);
                     }
                 } else {
-                    if !self.is_truthy(&left) {
+                    if !operators::is_truthy(&left) {
                         return Ok(left);
                     }
                 }
 
                 Ok(self.evaluate(right)?)
             }
-        }
-    }
+            Expr::Get { object, name } => {
+                let object = self.evaluate(object)?;
+
+                get_property(object, name)
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                operator,
+            } => {
+                let object = self.evaluate(object)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let current = get_property(object.to_owned(), name)?;
+                        let value = self.evaluate(value)?;
+
+                        operators::binary(operator, current, value)?
+                    }
+                    None => self.evaluate(value)?,
+                };
+
+                match object {
+                    LiteralType::Instance(instance) => {
+                        instance.borrow_mut().set(name, value.to_owned());
 
-    fn is_truthy(&self, object: &LiteralType) -> bool {
-        match *object {
-            LiteralType::Bool(value) => value,
-            _ => false,
+                        Ok(value)
+                    }
+                    _ => Err(RuntimeError::new(
+                        name.to_owned(),
+                        "only instances have fields".to_owned(),
+                    )),
+                }
+            }
+            Expr::List(items) => {
+                let mut values: Vec<LiteralType> = Vec::new();
+
+                for item in items {
+                    values.push(self.evaluate(item)?);
+                }
+
+                Ok(LiteralType::List(Rc::new(RefCell::new(values))))
+            }
+            Expr::Interpolation(parts) => {
+                let mut string = String::new();
+
+                for part in parts {
+                    string += self.evaluate(part)?.to_string().as_str();
+                }
+
+                Ok(LiteralType::String(string))
+            }
+            Expr::Map { brace, entries } => {
+                let mut map = Map::default();
+
+                for (key, value) in entries {
+                    let key = self.evaluate(key)?;
+                    let value = self.evaluate(value)?;
+
+                    map.insert(key.to_key(brace)?, key, value);
+                }
+
+                Ok(LiteralType::Map(Rc::new(RefCell::new(map))))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+
+                operators::index(bracket, object, index)
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+                operator,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = match operator {
+                    Some(operator) => {
+                        let current =
+                            operators::index(bracket, object.to_owned(), index.to_owned())?;
+                        let value = self.evaluate(value)?;
+
+                        operators::binary(operator, current, value)?
+                    }
+                    None => self.evaluate(value)?,
+                };
+
+                operators::set_index(bracket, object, index, value)
+            }
+            Expr::Lambda(function) => Ok(LiteralType::Function {
+                deceleration: function.clone(),
+                closure: self.environment.clone(),
+                is_initializer: false,
+            }),
+            Expr::This { keyword, binding } => self.look_up_variable(keyword, binding),
+            Expr::Super {
+                keyword: _,
+                method,
+                binding,
+            } => {
+                // The resolver always puts 'this' in the scope right inside the one holding 'super'.
+                let Binding { depth, slot } = binding.get().unwrap();
+                let superclass = self.environment.borrow().get_at(depth, slot);
+                let this = self.environment.borrow().get_at(depth - 1, 0);
+
+                match superclass {
+                    LiteralType::Class(superclass) => {
+                        match superclass.find_method(&method.lexeme) {
+                            Some(method) => Ok(method.bind(this)),
+                            None => Err(RuntimeError::new(
+                                method.to_owned(),
+                                format!("undefined property '{}'", method.lexeme),
+                            )),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Match {
+                keyword,
+                value,
+                arms,
+            } => {
+                let value = self.evaluate(value)?;
+                let enclosing = self.environment.clone();
+
+                for arm in arms {
+                    let mut bindings = Vec::new();
+
+                    if !operators::match_pattern(&arm.pattern, &value, &mut bindings) {
+                        continue;
+                    }
+
+                    self.environment = Environment::with_enclosing(enclosing.clone());
+
+                    for (name, binding) in arm.pattern.bindings().into_iter().zip(bindings) {
+                        self.environment
+                            .borrow_mut()
+                            .define(name.lexeme.to_owned(), binding);
+                    }
+
+                    let matched = match &arm.guard {
+                        Some(guard) => operators::is_truthy(&self.evaluate(guard)?),
+                        None => true,
+                    };
+
+                    let result = if matched {
+                        Some(self.evaluate(&arm.body)?)
+                    } else {
+                        None
+                    };
+
+                    self.environment = enclosing.clone();
+
+                    if let Some(result) = result {
+                        return Ok(result);
+                    }
+                }
+
+                Err(RuntimeError::new(
+                    keyword.to_owned(),
+                    format!("no match arm for {}", value.to_repr()),
+                ))
+            }
         }
     }
 
-    fn check_number_operand(
+    fn look_up_variable(
         &self,
-        operator: &Token,
-        operand: &LiteralType,
-    ) -> Result<(), RuntimeError> {
-        match operand {
-            LiteralType::Number(_) => Ok(()),
-            _ => Err(RuntimeError::new(
-                operator.to_owned(),
-                "operand must be a number".to_string(),
-            )),
+        name: &Token,
+        binding: &Cell<Option<Binding>>,
+    ) -> Result<LiteralType, RuntimeError> {
+        match binding.get() {
+            Some(Binding { depth, slot }) => Ok(self.environment.borrow().get_at(depth, slot)),
+            // Globals are whichever ones the running code was defined with,
+            // which differ between modules.
+            None => self.environment.borrow().get(name),
         }
     }
+}
 
-    fn check_number_operands(
-        &self,
-        operator: &Token,
-        left: &LiteralType,
-        right: &LiteralType,
-    ) -> Result<(), RuntimeError> {
-        match (left, right) {
-            (LiteralType::Number(_), LiteralType::Number(_)) => Ok(()),
-            _ => Err(RuntimeError::new(
-                operator.to_owned(),
-                "operands must be a number".to_string(),
-            )),
-        }
+fn get_property(object: LiteralType, name: &Token) -> Result<LiteralType, RuntimeError> {
+    match object {
+        LiteralType::Instance(instance) => Instance::get(&instance, name),
+        LiteralType::Module(namespace) => namespace.get(name),
+        _ => Err(RuntimeError::new(
+            name.to_owned(),
+            "only instances have properties".to_owned(),
+        )),
     }
+}
 
-    fn is_equal(
-        &self,
-        operator: &Token,
-        left: LiteralType,
-        right: LiteralType,
-    ) -> Result<bool, RuntimeError> {
-        match (left, right) {
-            (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
-                Ok(left_value == right_value)
-            }
-            (LiteralType::String(left_value), LiteralType::String(right_value)) => {
-                Ok(left_value == right_value)
-            }
-            _ => Err(RuntimeError::new(
-                operator.to_owned(),
-                "cannot compare".to_owned(),
-            )),
-        }
+fn global_environment() -> Rc<RefCell<Environment>> {
+    let mut environment = Environment::new();
+
+    for (name, function) in stdlib::functions() {
+        environment.define(name, function);
     }
+
+    Rc::new(RefCell::new(environment))
 }
@@ -7,6 +7,7 @@ use fnv::FnvHashMap;
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
     pub values: FnvHashMap<String, LiteralType>,
+    pub slots: Vec<LiteralType>,
 }
 
 impl Environment {
@@ -14,6 +15,7 @@ impl Environment {
         Environment {
             enclosing: None,
             values: FnvHashMap::default(),
+            slots: Vec::new(),
         }
     }
 
@@ -21,16 +23,49 @@ impl Environment {
         Rc::new(RefCell::new(Environment {
             enclosing: Some(enclosing),
             values: FnvHashMap::default(),
+            slots: Vec::new(),
         }))
     }
 
+    // Globals are looked up by name, everything else by the slot the resolver
+    // gave it, which is the order the values are defined in.
     pub fn define(&mut self, name: String, value: LiteralType) {
-        self.values.insert(name, value);
+        if self.enclosing.is_some() {
+            self.slots.push(value);
+        } else {
+            self.values.insert(name, value);
+        }
+    }
+
+    pub fn get_at(&self, depth: usize, slot: usize) -> LiteralType {
+        if depth == 0 {
+            return self.slots[slot].to_owned();
+        }
+
+        self.enclosing
+            .as_ref()
+            .unwrap()
+            .borrow()
+            .get_at(depth - 1, slot)
+    }
+
+    pub fn assign_at(&mut self, depth: usize, slot: usize, value: LiteralType) {
+        if depth == 0 {
+            self.slots[slot] = value;
+
+            return;
+        }
+
+        self.enclosing
+            .as_ref()
+            .unwrap()
+            .borrow_mut()
+            .assign_at(depth - 1, slot, value);
     }
 
     pub fn assign(&mut self, name: Token, value: LiteralType) -> Result<(), RuntimeError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
 
             return Ok(());
         }
@@ -1,4 +1,4 @@
-use std::{io::Write, rc::Rc};
+use std::{cell::RefCell, io::Write, rc::Rc};
 
 use crate::{error::RuntimeError, types::LiteralType};
 
@@ -7,6 +7,27 @@ pub trait StdLibFunc {
     fn function() -> LiteralType;
 }
 
+pub fn functions() -> Vec<(String, LiteralType)> {
+    vec![
+        (Input::name(), Input::function()),
+        (InputPrompt::name(), InputPrompt::function()),
+        (PrintLn::name(), PrintLn::function()),
+        (Print::name(), Print::function()),
+        (Int::name(), Int::function()),
+        (Float::name(), Float::function()),
+        (Str::name(), Str::function()),
+        (Len::name(), Len::function()),
+        (Push::name(), Push::function()),
+        (Pop::name(), Pop::function()),
+        (Insert::name(), Insert::function()),
+        (Remove::name(), Remove::function()),
+        (Keys::name(), Keys::function()),
+        (Values::name(), Values::function()),
+        (Has::name(), Has::function()),
+        (Delete::name(), Delete::function()),
+    ]
+}
+
 pub struct Print;
 
 impl StdLibFunc for Print {
@@ -113,10 +134,52 @@ impl StdLibFunc for Int {
                 let error = RuntimeError::new(paren, "cannot cast to int".to_owned());
 
                 match &args[0] {
-                    LiteralType::Number(value) => Ok(LiteralType::Number(*value as i32 as f32)),
-                    LiteralType::String(value) => match value.parse::<f32>() {
-                        Ok(value) => Ok(LiteralType::Number(value as i32 as f32)),
-                        Err(_) => return Err(error),
+                    LiteralType::Int(value) => Ok(LiteralType::Int(*value)),
+                    LiteralType::Number(value) => float_to_int(*value).ok_or(error),
+                    LiteralType::String(value) => match value.parse::<i64>() {
+                        Ok(value) => Ok(LiteralType::Int(value)),
+                        Err(_) => match value.parse::<f64>() {
+                            Ok(value) => float_to_int(value).ok_or(error),
+                            Err(_) => Err(error),
+                        },
+                    },
+                    _ => Err(error),
+                }
+            }),
+        }
+    }
+}
+
+// Truncates towards zero; NaN, infinities and floats outside the i64 range
+// have no int to truncate to.
+fn float_to_int(value: f64) -> Option<LiteralType> {
+    if value.is_finite() && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+        Some(LiteralType::Int(value as i64))
+    } else {
+        None
+    }
+}
+
+pub struct Float;
+
+impl StdLibFunc for Float {
+    fn name() -> String {
+        "float".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "float".to_owned(),
+            arity: 1,
+            func: Rc::new(|args, paren| {
+                let error = RuntimeError::new(paren, "cannot cast to float".to_owned());
+
+                match &args[0] {
+                    LiteralType::Int(value) => Ok(LiteralType::Number(*value as f64)),
+                    LiteralType::Number(value) => Ok(LiteralType::Number(*value)),
+                    LiteralType::String(value) => match value.parse::<f64>() {
+                        Ok(value) => Ok(LiteralType::Number(value)),
+                        Err(_) => Err(error),
                     },
                     _ => Err(error),
                 }
@@ -140,11 +203,222 @@ impl StdLibFunc for Str {
                 let error = RuntimeError::new(paren, "cannot cast to string".to_owned());
 
                 match &args[0] {
-                    LiteralType::Number(value) => Ok(LiteralType::String(format!("{}", value))),
-                    LiteralType::String(value) => Ok(LiteralType::String(format!("{}", value))),
+                    LiteralType::Int(_) | LiteralType::Number(_) => {
+                        Ok(LiteralType::String(args[0].to_string()))
+                    }
+                    LiteralType::String(value) => Ok(LiteralType::String(value.to_string())),
                     _ => Err(error),
                 }
             }),
         }
     }
 }
+
+pub struct Len;
+
+impl StdLibFunc for Len {
+    fn name() -> String {
+        "len".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "len".to_owned(),
+            arity: 1,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::List(items) => Ok(LiteralType::Int(items.borrow().len() as i64)),
+                LiteralType::Map(map) => Ok(LiteralType::Int(map.borrow().len() as i64)),
+                LiteralType::String(value) => Ok(LiteralType::Int(value.chars().count() as i64)),
+                _ => Err(RuntimeError::new(
+                    paren,
+                    "expected a list, a map or a string".to_owned(),
+                )),
+            }),
+        }
+    }
+}
+
+pub struct Push;
+
+impl StdLibFunc for Push {
+    fn name() -> String {
+        "push".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "push".to_owned(),
+            arity: 2,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::List(items) => {
+                    items.borrow_mut().push(args[1].to_owned());
+                    Ok(LiteralType::Nil)
+                }
+                _ => Err(RuntimeError::new(paren, "expected a list".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Pop;
+
+impl StdLibFunc for Pop {
+    fn name() -> String {
+        "pop".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "pop".to_owned(),
+            arity: 1,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::List(items) => match items.borrow_mut().pop() {
+                    Some(value) => Ok(value),
+                    None => Err(RuntimeError::new(
+                        paren,
+                        "cannot pop from an empty list".to_owned(),
+                    )),
+                },
+                _ => Err(RuntimeError::new(paren, "expected a list".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Insert;
+
+impl StdLibFunc for Insert {
+    fn name() -> String {
+        "insert".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "insert".to_owned(),
+            arity: 3,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::List(items) => {
+                    let mut items = items.borrow_mut();
+                    // Inserting at the end is allowed, so check against `len + 1`.
+                    let index = args[1].to_index(items.len() + 1, &paren)?;
+
+                    items.insert(index, args[2].to_owned());
+                    Ok(LiteralType::Nil)
+                }
+                _ => Err(RuntimeError::new(paren, "expected a list".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Remove;
+
+impl StdLibFunc for Remove {
+    fn name() -> String {
+        "remove".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "remove".to_owned(),
+            arity: 2,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::List(items) => {
+                    let mut items = items.borrow_mut();
+                    let index = args[1].to_index(items.len(), &paren)?;
+
+                    Ok(items.remove(index))
+                }
+                _ => Err(RuntimeError::new(paren, "expected a list".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Keys;
+
+impl StdLibFunc for Keys {
+    fn name() -> String {
+        "keys".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "keys".to_owned(),
+            arity: 1,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::Map(map) => Ok(LiteralType::List(Rc::new(RefCell::new(
+                    map.borrow().keys(),
+                )))),
+                _ => Err(RuntimeError::new(paren, "expected a map".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Values;
+
+impl StdLibFunc for Values {
+    fn name() -> String {
+        "values".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "values".to_owned(),
+            arity: 1,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::Map(map) => Ok(LiteralType::List(Rc::new(RefCell::new(
+                    map.borrow().values(),
+                )))),
+                _ => Err(RuntimeError::new(paren, "expected a map".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Has;
+
+impl StdLibFunc for Has {
+    fn name() -> String {
+        "has".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "has".to_owned(),
+            arity: 2,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::Map(map) => {
+                    let key = args[1].to_key(&paren)?;
+
+                    Ok(LiteralType::Bool(map.borrow().contains(&key)))
+                }
+                _ => Err(RuntimeError::new(paren, "expected a map".to_owned())),
+            }),
+        }
+    }
+}
+
+pub struct Delete;
+
+impl StdLibFunc for Delete {
+    fn name() -> String {
+        "delete".to_owned()
+    }
+
+    fn function() -> LiteralType {
+        LiteralType::NativeFunction {
+            name: "delete".to_owned(),
+            arity: 2,
+            func: Rc::new(|args, paren| match &args[0] {
+                LiteralType::Map(map) => {
+                    let key = args[1].to_key(&paren)?;
+
+                    Ok(map.borrow_mut().remove(&key).unwrap_or(LiteralType::Nil))
+                }
+                _ => Err(RuntimeError::new(paren, "expected a map".to_owned())),
+            }),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Wind is a small dynamically typed scripting language.
+//!
+//! Besides the `wind-lang` binary, the crate can be embedded in other Rust
+//! programs. An [`Interpreter`] keeps its global scope between calls, so
+//! values and natives registered from Rust are visible to every script it
+//! runs afterwards.
+//!
+//! ```
+//! use std::convert::TryFrom;
+//!
+//! use wind_lang::{Interpreter, LiteralType};
+//!
+//! let mut interpreter = Interpreter::new();
+//!
+//! interpreter.set_global("base", 40.0);
+//! interpreter.register_native("double", 1, |args, _paren| {
+//!     let value = f64::try_from(args[0].to_owned()).unwrap_or(0.0);
+//!
+//!     Ok(LiteralType::from(value * 2.0))
+//! });
+//!
+//! interpreter.run("var answer = base + double(1);").unwrap();
+//!
+//! let answer = interpreter.get_global("answer").unwrap();
+//! assert_eq!(f64::try_from(answer).unwrap(), 42.0);
+//! ```
+
+mod ast;
+mod diagnostic;
+mod error;
+mod interpreter;
+mod module;
+mod operators;
+mod parser;
+mod resolver;
+mod scanner;
+mod token;
+mod types;
+mod vm;
+
+pub use diagnostic::Diagnostic;
+pub use error::{Error, ParseError, RuntimeError, ScannerError, WindError};
+pub use interpreter::Interpreter;
+pub use token::{Source, Span, Token, TokenType};
+pub use types::LiteralType;
+pub use vm::Vm;
+
+use std::{fs, path::Path, rc::Rc};
+
+use ast::Stmt;
+use module::Loader;
+use parser::Parser;
+use resolver::Resolver;
+use scanner::Scanner;
+
+/// A script that has been scanned, parsed and resolved, ready for either
+/// backend to run.
+pub struct Program {
+    pub(crate) statements: Vec<Stmt>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    /// Things that don't stop the program from running but are likely
+    /// mistakes, like a match arm that can never be reached. They cover the
+    /// modules the program imports too.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+}
+
+/// Splits `source` into tokens without parsing them.
+pub fn scan(source: Rc<Source>) -> Result<Vec<Token>, Error> {
+    Ok(Scanner::new(source).scan_tokens()?)
+}
+
+/// Scans, parses and resolves `source` without running it.
+pub fn parse(source: &str) -> Result<Program, Error> {
+    parse_source(Source::new("<script>", source.to_owned()))
+}
+
+/// Reads and parses the file at `path`, which diagnostics and imports refer
+/// to it by.
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Program, Error> {
+    let code = fs::read_to_string(&path)?;
+
+    parse_source(Source::new(&path.as_ref().to_string_lossy(), code))
+}
+
+/// Like [`parse`], but diagnostics refer to the source by its name, which is
+/// also the path that imports are relative to.
+pub fn parse_source(source: Rc<Source>) -> Result<Program, Error> {
+    parse_program(source, false)
+}
+
+/// Like [`parse_source`], but the last statement can be an expression
+/// without its `;`, the way a line typed into the REPL reads.
+pub fn parse_repl(source: Rc<Source>) -> Result<Program, Error> {
+    parse_program(source, true)
+}
+
+fn parse_program(source: Rc<Source>, trailing_expression: bool) -> Result<Program, Error> {
+    let mut warnings = Vec::new();
+    let statements = parse_module(source.clone(), trailing_expression, &mut warnings)?;
+
+    let mut loader = Loader::new(&source);
+    loader.load(&statements)?;
+    warnings.append(&mut loader.warnings);
+
+    Ok(Program {
+        statements,
+        warnings,
+    })
+}
+
+// Everything `parse_source` does except loading the modules it imports.
+pub(crate) fn parse_module(
+    source: Rc<Source>,
+    trailing_expression: bool,
+    warnings: &mut Vec<Diagnostic>,
+) -> Result<Vec<Stmt>, Error> {
+    let tokens = scan(source)?;
+
+    let mut parser = Parser::new(tokens);
+
+    if trailing_expression {
+        parser = parser.allow_trailing_expression();
+    }
+
+    let ast = parser.parse()?;
+    warnings.extend(parser.take_warnings());
+
+    Resolver::new().resolve(&ast)?;
+
+    Ok(ast)
+}
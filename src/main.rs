@@ -1,19 +1,12 @@
-use crate::parser::Parser;
 use clap::{App, Arg};
-use interpreter::Interpreter;
 // use pprof::protos::Message;
-use scanner::Scanner;
-use std::fs;
+use repl::Repl;
+use std::process;
+use wind_lang::{Interpreter, Vm, WindError};
 // use std::fs::File;
 // use std::io::Write;
 
-mod ast;
-mod error;
-mod interpreter;
-mod parser;
-mod scanner;
-mod token;
-mod types;
+mod repl;
 
 fn main() {
     // let guard = pprof::ProfilerGuard::new(997).unwrap();
@@ -29,19 +22,36 @@ fn main() {
                 .value_name("FILE")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .short('b')
+                .about("How to run FILE: walk the syntax tree or compile it to bytecode")
+                .value_name("BACKEND")
+                .possible_values(["tree", "vm"])
+                .default_value("tree")
+                .takes_value(true),
+        )
         .get_matches();
 
     if let Some(file_name) = matches.value_of("file") {
-        let code = fs::read_to_string(file_name).unwrap();
-
-        let mut scanner = Scanner::new(code);
-        let tokens = scanner.scan_tokens();
+        let result = wind_lang::parse_file(file_name).and_then(|program| {
+            for warning in program.warnings() {
+                eprintln!("{}\n", warning);
+            }
 
-        let mut parser = Parser::new(tokens);
-        let ast = parser.parse();
+            match matches.value_of("backend") {
+                Some("vm") => Vm::new().interpret(&program),
+                _ => Ok(Interpreter::new().interpret(&program)?),
+            }
+        });
 
-        let mut interpreter = Interpreter::new();
-        interpreter.interpret(ast);
+        if let Err(e) = result {
+            e.report();
+            process::exit(e.exit_code());
+        }
+    } else {
+        Repl::new().run();
     }
 
     // if let Ok(report) = guard.report().build() {
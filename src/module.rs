@@ -0,0 +1,184 @@
+use std::{
+    fs, iter,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use fnv::FnvHashMap;
+
+use crate::{
+    ast::Stmt,
+    diagnostic::Diagnostic,
+    error::{Error, ParseError, RuntimeError},
+    token::{Source, Token},
+    types::{LiteralType, ModuleGlobals, Namespace},
+};
+
+// A parsed and resolved `.wind` file that some program imports. Both backends
+// run it the first time it's imported and keep what it exported.
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub statements: Vec<Stmt>,
+    pub exports: Vec<String>,
+}
+
+// Loads every module a program imports before any of it runs, so a missing
+// file, an import cycle or a name the module doesn't export is reported up
+// front, like a parse error.
+pub(crate) struct Loader {
+    // The files being loaded, outermost first, with the names they were
+    // imported by.
+    loading: Vec<(PathBuf, String)>,
+    modules: FnvHashMap<PathBuf, Rc<Module>>,
+    pub(crate) warnings: Vec<Diagnostic>,
+}
+
+impl Loader {
+    pub(crate) fn new(source: &Source) -> Loader {
+        let loading = match fs::canonicalize(&source.name) {
+            Ok(path) => vec![(path, source.name.to_owned())],
+            Err(_) => Vec::new(),
+        };
+
+        Loader {
+            loading,
+            modules: FnvHashMap::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn load(&mut self, statements: &[Stmt]) -> Result<(), Error> {
+        for statement in statements {
+            if let Stmt::Import {
+                path,
+                names,
+                module,
+                ..
+            } = statement
+            {
+                let loaded = self.import(path)?;
+
+                for name in names {
+                    if !loaded.exports.contains(&name.lexeme) {
+                        return Err(error(
+                            name,
+                            format!(
+                                "module '{}' has no export named '{}'",
+                                loaded.name, name.lexeme
+                            ),
+                        ));
+                    }
+                }
+
+                let _ = module.set(loaded);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn import(&mut self, path: &Token) -> Result<Rc<Module>, Error> {
+        let relative = match path.literal.as_ref() {
+            LiteralType::String(relative) => relative,
+            _ => unreachable!(),
+        };
+
+        // Paths are relative to the file doing the importing.
+        let file = Path::new(&path.span.source.name)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(relative);
+        let file_name = file.to_string_lossy().into_owned();
+
+        let canonical = fs::canonicalize(&file)
+            .map_err(|e| error(path, format!("cannot import '{}': {}", file_name, e)))?;
+
+        if let Some(start) = self
+            .loading
+            .iter()
+            .position(|(loading, _)| *loading == canonical)
+        {
+            let cycle: Vec<&str> = self.loading[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain(iter::once(file_name.as_str()))
+                .collect();
+
+            return Err(error(path, format!("import cycle: {}", cycle.join(" -> "))));
+        }
+
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+
+        let code = fs::read_to_string(&file)
+            .map_err(|e| error(path, format!("cannot import '{}': {}", file_name, e)))?;
+        let statements =
+            crate::parse_module(Source::new(&file_name, code), false, &mut self.warnings)?;
+
+        self.loading.push((canonical.to_owned(), file_name));
+        let result = self.load(&statements);
+        self.loading.pop();
+        result?;
+
+        let module = Rc::new(Module {
+            name: file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: canonical.to_owned(),
+            exports: exports(&statements),
+            statements,
+        });
+
+        self.modules.insert(canonical, module.clone());
+
+        Ok(module)
+    }
+}
+
+// The namespace of every module a backend has run, by path.
+pub(crate) type Modules = FnvHashMap<PathBuf, Rc<Namespace>>;
+
+// Importing works the same on both backends: `run` executes the module the
+// first time, with globals of its own, and returns them. That import and
+// every later one get a namespace reading from those globals.
+pub(crate) fn import<B>(
+    backend: &mut B,
+    modules: fn(&mut B) -> &mut Modules,
+    module: &Rc<Module>,
+    run: impl FnOnce(&mut B) -> Result<ModuleGlobals, RuntimeError>,
+) -> Result<Rc<Namespace>, RuntimeError> {
+    if let Some(namespace) = modules(backend).get(&module.path) {
+        return Ok(namespace.clone());
+    }
+
+    let namespace = Rc::new(Namespace {
+        module: module.clone(),
+        globals: run(backend)?,
+    });
+
+    modules(backend).insert(module.path.to_owned(), namespace.clone());
+
+    Ok(namespace)
+}
+
+fn exports(statements: &[Stmt]) -> Vec<String> {
+    statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Stmt::Export { declaration, .. } => match declaration.as_ref() {
+                Stmt::VarDecl { name, .. } | Stmt::Class { name, .. } => Some(name),
+                Stmt::FunctionDecl(function) => function.name.as_ref(),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|name| name.lexeme.to_owned())
+        .collect()
+}
+
+fn error(token: &Token, message: String) -> Error {
+    Error::Parse(vec![ParseError::new(token.to_owned(), message)])
+}
@@ -0,0 +1,399 @@
+use std::convert::TryFrom;
+
+use crate::{
+    ast::Pattern,
+    error::RuntimeError,
+    token::{Token, TokenType},
+    types::LiteralType,
+};
+
+pub fn binary(
+    operator: &Token,
+    left: LiteralType,
+    right: LiteralType,
+) -> Result<LiteralType, RuntimeError> {
+    match operator.t_type {
+        TokenType::Minus | TokenType::MinusEqual => match number_operands(operator, &left, &right)?
+        {
+            Operands::Int(left_value, right_value) => {
+                check_overflow(operator, left_value.checked_sub(right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Number(left_value - right_value))
+            }
+        },
+        TokenType::Slash | TokenType::SlashEqual => match number_operands(operator, &left, &right)?
+        {
+            Operands::Int(_, 0) => Err(division_by_zero(operator)),
+            Operands::Int(left_value, right_value) => {
+                check_overflow(operator, left_value.checked_div(right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Number(left_value / right_value))
+            }
+        },
+        TokenType::Star | TokenType::StarEqual => match number_operands(operator, &left, &right)? {
+            Operands::Int(left_value, right_value) => {
+                check_overflow(operator, left_value.checked_mul(right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Number(left_value * right_value))
+            }
+        },
+        TokenType::Percent | TokenType::PercentEqual => {
+            match number_operands(operator, &left, &right)? {
+                Operands::Int(_, 0) => Err(division_by_zero(operator)),
+                Operands::Int(left_value, right_value) => {
+                    check_overflow(operator, left_value.checked_rem(right_value))
+                }
+                Operands::Float(left_value, right_value) => {
+                    Ok(LiteralType::Number(left_value % right_value))
+                }
+            }
+        }
+        TokenType::StarStar | TokenType::StarStarEqual => {
+            match number_operands(operator, &left, &right)? {
+                Operands::Int(base, exponent) if exponent >= 0 => {
+                    let value = u32::try_from(exponent)
+                        .ok()
+                        .and_then(|exponent| base.checked_pow(exponent));
+
+                    check_overflow(operator, value)
+                }
+                // A negative exponent has no int result.
+                Operands::Int(base, exponent) => {
+                    Ok(LiteralType::Number((base as f64).powf(exponent as f64)))
+                }
+                Operands::Float(base, exponent) => Ok(LiteralType::Number(base.powf(exponent))),
+            }
+        }
+        TokenType::Ampersand | TokenType::AmpersandEqual => {
+            let (left_value, right_value) = int_operands(operator, &left, &right)?;
+
+            Ok(LiteralType::Int(left_value & right_value))
+        }
+        TokenType::Pipe | TokenType::PipeEqual => {
+            let (left_value, right_value) = int_operands(operator, &left, &right)?;
+
+            Ok(LiteralType::Int(left_value | right_value))
+        }
+        TokenType::Caret | TokenType::CaretEqual => {
+            let (left_value, right_value) = int_operands(operator, &left, &right)?;
+
+            Ok(LiteralType::Int(left_value ^ right_value))
+        }
+        TokenType::LessLess | TokenType::LessLessEqual => {
+            let (value, shift) = int_operands(operator, &left, &right)?;
+            let shift = u32::try_from(shift)
+                .ok()
+                .filter(|shift| *shift < i64::BITS)
+                .ok_or_else(|| invalid_shift(operator))?;
+
+            // Shifting back has to give the value again, or bits were lost.
+            let shifted = value << shift;
+
+            check_overflow(
+                operator,
+                Some(shifted).filter(|_| shifted >> shift == value),
+            )
+        }
+        TokenType::GreaterGreater | TokenType::GreaterGreaterEqual => {
+            let (value, shift) = int_operands(operator, &left, &right)?;
+            let value = u32::try_from(shift)
+                .ok()
+                .and_then(|shift| value.checked_shr(shift));
+
+            value
+                .map(LiteralType::Int)
+                .ok_or_else(|| invalid_shift(operator))
+        }
+        TokenType::Greater => match number_operands(operator, &left, &right)? {
+            Operands::Int(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value > right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value > right_value))
+            }
+        },
+        TokenType::GreaterEqual => match number_operands(operator, &left, &right)? {
+            Operands::Int(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value >= right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value >= right_value))
+            }
+        },
+        TokenType::Less => match number_operands(operator, &left, &right)? {
+            Operands::Int(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value < right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value < right_value))
+            }
+        },
+        TokenType::LessEqual => match number_operands(operator, &left, &right)? {
+            Operands::Int(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value <= right_value))
+            }
+            Operands::Float(left_value, right_value) => {
+                Ok(LiteralType::Bool(left_value <= right_value))
+            }
+        },
+        TokenType::In => match right {
+            LiteralType::Map(map) => Ok(LiteralType::Bool(
+                map.borrow().contains(&left.to_key(operator)?),
+            )),
+            _ => Err(RuntimeError::new(
+                operator.to_owned(),
+                "right operand of 'in' must be a map".to_owned(),
+            )),
+        },
+        TokenType::EqualEqual => Ok(LiteralType::Bool(left == right)),
+        TokenType::BangEqual => Ok(LiteralType::Bool(left != right)),
+        TokenType::Plus | TokenType::PlusEqual => match (&left, &right) {
+            (LiteralType::String(left_value), LiteralType::String(right_value)) => {
+                let res = [left_value.to_owned(), right_value.to_owned()].join("");
+                Ok(LiteralType::String(res))
+            }
+            _ => match number_operands(operator, &left, &right) {
+                Ok(Operands::Int(left_value, right_value)) => {
+                    check_overflow(operator, left_value.checked_add(right_value))
+                }
+                Ok(Operands::Float(left_value, right_value)) => {
+                    Ok(LiteralType::Number(left_value + right_value))
+                }
+                Err(_) => Err(RuntimeError::new(
+                    operator.to_owned(),
+                    "cannot add".to_owned(),
+                )),
+            },
+        },
+
+        _ => unreachable!(),
+    }
+}
+
+pub fn unary(operator: &Token, right: LiteralType) -> Result<LiteralType, RuntimeError> {
+    match operator.t_type {
+        TokenType::Bang => Ok(LiteralType::Bool(!is_truthy(&right))),
+        TokenType::Minus => match right {
+            LiteralType::Int(value) => check_overflow(operator, value.checked_neg()),
+            LiteralType::Number(value) => Ok(LiteralType::Number(-value)),
+            _ => Err(RuntimeError::new(
+                operator.to_owned(),
+                "operand must be a number".to_string(),
+            )),
+        },
+        TokenType::Tilde => match right {
+            LiteralType::Int(value) => Ok(LiteralType::Int(!value)),
+            _ => Err(RuntimeError::new(
+                operator.to_owned(),
+                "operand must be an int".to_string(),
+            )),
+        },
+        _ => unreachable!(),
+    }
+}
+
+pub fn index(
+    bracket: &Token,
+    object: LiteralType,
+    index: LiteralType,
+) -> Result<LiteralType, RuntimeError> {
+    match object {
+        LiteralType::List(items) => {
+            let items = items.borrow();
+            let index = index.to_index(items.len(), bracket)?;
+
+            Ok(items[index].to_owned())
+        }
+        LiteralType::String(string_value) => {
+            let chars: Vec<char> = string_value.chars().collect();
+            let index = index.to_index(chars.len(), bracket)?;
+
+            Ok(LiteralType::String(chars[index].to_string()))
+        }
+        LiteralType::Map(map) => match map.borrow().get(&index.to_key(bracket)?) {
+            Some(value) => Ok(value.to_owned()),
+            None => Err(RuntimeError::new(
+                bracket.to_owned(),
+                format!("key {} not found", index.to_repr()),
+            )),
+        },
+        _ => Err(RuntimeError::new(
+            bracket.to_owned(),
+            "can only index lists, maps and strings".to_owned(),
+        )),
+    }
+}
+
+pub fn set_index(
+    bracket: &Token,
+    object: LiteralType,
+    index: LiteralType,
+    value: LiteralType,
+) -> Result<LiteralType, RuntimeError> {
+    match object {
+        LiteralType::List(items) => {
+            let mut items = items.borrow_mut();
+            let index = index.to_index(items.len(), bracket)?;
+
+            items[index] = value.to_owned();
+
+            Ok(value)
+        }
+        LiteralType::Map(map) => {
+            let key = index.to_key(bracket)?;
+
+            map.borrow_mut().insert(key, index, value.to_owned());
+
+            Ok(value)
+        }
+        _ => Err(RuntimeError::new(
+            bracket.to_owned(),
+            "can only assign to list and map items".to_owned(),
+        )),
+    }
+}
+
+pub fn iterate(name: &Token, iterable: LiteralType) -> Result<Vec<LiteralType>, RuntimeError> {
+    match iterable {
+        LiteralType::List(items) => Ok(items.borrow().to_owned()),
+        LiteralType::Map(map) => Ok(map.borrow().keys()),
+        LiteralType::String(value) => Ok(value
+            .chars()
+            .map(|c| LiteralType::String(c.to_string()))
+            .collect()),
+        _ => Err(RuntimeError::new(
+            name.to_owned(),
+            "can only iterate over lists, maps and strings".to_owned(),
+        )),
+    }
+}
+
+pub fn is_truthy(object: &LiteralType) -> bool {
+    !matches!(object, LiteralType::Nil | LiteralType::Bool(false))
+}
+
+// Whether `value` matches `pattern`. The values it binds are pushed onto
+// `bindings` in the order `Pattern::bindings` names them.
+pub fn match_pattern(
+    pattern: &Pattern,
+    value: &LiteralType,
+    bindings: &mut Vec<LiteralType>,
+) -> bool {
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Literal(literal) => value == literal,
+        Pattern::Range(start, end) => {
+            let number = |value: &LiteralType| f64::try_from(value.to_owned()).ok();
+
+            match (number(start), number(value), number(end)) {
+                (Some(start), Some(value), Some(end)) => start <= value && value <= end,
+                _ => false,
+            }
+        }
+        Pattern::Binding(_) => {
+            bindings.push(value.to_owned());
+            true
+        }
+        Pattern::List { items, rest } => {
+            let list = match value {
+                LiteralType::List(list) => list.borrow(),
+                _ => return false,
+            };
+
+            let length_matches = match rest {
+                Some(_) => list.len() >= items.len(),
+                None => list.len() == items.len(),
+            };
+
+            length_matches
+                && items
+                    .iter()
+                    .zip(list.iter())
+                    .all(|(item, value)| match_pattern(item, value, bindings))
+                && rest.as_ref().is_none_or(|rest| {
+                    let rest_value = LiteralType::from(list[items.len()..].to_vec());
+
+                    match_pattern(rest, &rest_value, bindings)
+                })
+        }
+        Pattern::Map(entries) => {
+            let map = match value {
+                LiteralType::Map(map) => map.borrow(),
+                _ => return false,
+            };
+
+            entries.iter().all(|(key, pattern)| {
+                key.as_key()
+                    .and_then(|key| map.get(&key))
+                    .is_some_and(|value| match_pattern(pattern, value, bindings))
+            })
+        }
+    }
+}
+
+enum Operands {
+    Int(i64, i64),
+    Float(f64, f64),
+}
+
+// Two ints stay ints; an int mixed with a float is promoted to a float.
+fn number_operands(
+    operator: &Token,
+    left: &LiteralType,
+    right: &LiteralType,
+) -> Result<Operands, RuntimeError> {
+    match (left, right) {
+        (LiteralType::Int(left_value), LiteralType::Int(right_value)) => {
+            Ok(Operands::Int(*left_value, *right_value))
+        }
+        (LiteralType::Int(left_value), LiteralType::Number(right_value)) => {
+            Ok(Operands::Float(*left_value as f64, *right_value))
+        }
+        (LiteralType::Number(left_value), LiteralType::Int(right_value)) => {
+            Ok(Operands::Float(*left_value, *right_value as f64))
+        }
+        (LiteralType::Number(left_value), LiteralType::Number(right_value)) => {
+            Ok(Operands::Float(*left_value, *right_value))
+        }
+        _ => Err(RuntimeError::new(
+            operator.to_owned(),
+            "operands must be a number".to_string(),
+        )),
+    }
+}
+
+fn int_operands(
+    operator: &Token,
+    left: &LiteralType,
+    right: &LiteralType,
+) -> Result<(i64, i64), RuntimeError> {
+    match (left, right) {
+        (LiteralType::Int(left_value), LiteralType::Int(right_value)) => {
+            Ok((*left_value, *right_value))
+        }
+        _ => Err(RuntimeError::new(
+            operator.to_owned(),
+            "operands must be ints".to_string(),
+        )),
+    }
+}
+
+fn check_overflow(operator: &Token, value: Option<i64>) -> Result<LiteralType, RuntimeError> {
+    value
+        .map(LiteralType::Int)
+        .ok_or_else(|| RuntimeError::new(operator.to_owned(), "integer overflow".to_owned()))
+}
+
+fn invalid_shift(operator: &Token) -> RuntimeError {
+    RuntimeError::new(
+        operator.to_owned(),
+        "shift amount must be between 0 and 63".to_owned(),
+    )
+}
+
+fn division_by_zero(operator: &Token) -> RuntimeError {
+    RuntimeError::new(operator.to_owned(), "division by zero".to_owned())
+}
@@ -1,53 +1,262 @@
-use std::rc::Rc;
+use std::{
+    cell::{Cell, OnceCell},
+    path::Path,
+    rc::Rc,
+};
 
 use crate::{
-    ast::{Expr, Stmt},
-    error::{ParseError, WindError},
-    token::{Token, TokenType},
+    ast::{Expr, Function, MatchArm, Pattern, Stmt},
+    diagnostic::Diagnostic,
+    error::ParseError,
+    operators,
+    token::{Span, Token, TokenType},
     types::LiteralType,
 };
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    loop_depth: usize,
+    errors: Vec<ParseError>,
+    warnings: Vec<Diagnostic>,
+    trailing_expression: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            trailing_expression: false,
+        }
+    }
+
+    // Lets the last statement be an expression without its ';', which is how
+    // a line typed into the REPL reads.
+    pub fn allow_trailing_expression(mut self) -> Parser {
+        self.trailing_expression = true;
+
+        self
+    }
+
+    // Things that don't stop the program from running but are likely mistakes,
+    // like a match arm that can never be reached.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
     }
 
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
         let mut statements: Vec<Stmt> = Vec::new();
 
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(decl) => {
-                    statements.push(decl);
-                }
-                Err(err) => {
-                    err.report();
-                }
-            };
+            if let Some(decl) = self.recovering_declaration() {
+                statements.push(decl);
+            }
+        }
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
         }
 
-        statements
+        Ok(statements)
+    }
+
+    fn recovering_declaration(&mut self) -> Option<Stmt> {
+        match self.declaration() {
+            Ok(decl) => Some(decl),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+
+                None
+            }
+        }
+    }
+
+    // Skips tokens until the start of the next statement so parsing can go on
+    // after an error and report the ones that follow it too.
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if self.previous().t_type == TokenType::Semicolon {
+                return;
+            }
+
+            match self.peak().t_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Import
+                | TokenType::Export
+                | TokenType::Throw
+                | TokenType::Try => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
     }
 
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_token(&[TokenType::Fun]) {
-            return Ok(self.function_declaration("function")?);
+        if self.match_token(&[TokenType::Import]) {
+            return self.import_declaration();
+        }
+
+        if self.match_token(&[TokenType::Export]) {
+            return self.export_declaration();
+        }
+
+        if self.match_token(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+
+        if self.check(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+            self.advance();
+
+            return Ok(Stmt::FunctionDecl(Rc::new(self.function("function")?)));
         }
 
         if self.match_token(&[TokenType::Var]) {
-            return Ok(self.var_declaration()?);
+            return self.var_declaration();
         }
 
         let statement = self.statement()?;
         Ok(statement)
     }
 
-    fn function_declaration(&mut self, kind: &'static str) -> Result<Stmt, ParseError> {
+    fn import_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let mut names: Vec<Token> = Vec::new();
+
+        if !self.check(TokenType::String) {
+            loop {
+                names.push(self.consume(TokenType::Identifier, "expect name to import")?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            if !self.match_contextual("from") {
+                return Err(ParseError::new(
+                    self.peak().to_owned(),
+                    "expect 'from' after imported names".to_owned(),
+                ));
+            }
+        }
+
+        let path = self.consume(TokenType::String, "expect module path")?;
+
+        let name = if !names.is_empty() {
+            None
+        } else if self.match_contextual("as") {
+            Some(self.consume(TokenType::Identifier, "expect module name after 'as'")?)
+        } else {
+            Some(self.module_name(&path)?)
+        };
+
+        self.consume(TokenType::Semicolon, "expect ';' after import")?;
+
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            name,
+            names,
+            module: OnceCell::new(),
+        })
+    }
+
+    // Without 'as', a module is named after its file, so "lib/math.wind" is
+    // bound to `math`.
+    fn module_name(&self, path: &Token) -> Result<Token, ParseError> {
+        let stem = match path.literal.as_ref() {
+            LiteralType::String(path) => Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            _ => unreachable!(),
+        };
+
+        let is_identifier = stem.chars().next().is_some_and(char::is_alphabetic)
+            && stem.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+        if !is_identifier {
+            return Err(ParseError::new(
+                path.to_owned(),
+                format!(
+                    "module name '{}' is not an identifier, name it with 'as'",
+                    stem
+                ),
+            ));
+        }
+
+        Ok(Token::new(
+            TokenType::Identifier,
+            stem,
+            Box::new(LiteralType::Nil),
+            path.span.to_owned(),
+        ))
+    }
+
+    fn export_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+
+        let is_declaration = self.check(TokenType::Var)
+            || self.check(TokenType::Class)
+            || (self.check(TokenType::Fun) && self.check_next(TokenType::Identifier));
+
+        if !is_declaration {
+            return Err(ParseError::new(
+                self.peak().to_owned(),
+                "expect a variable, function or class declaration after 'export'".to_owned(),
+            ));
+        }
+
+        Ok(Stmt::Export {
+            keyword,
+            declaration: Rc::new(self.declaration()?),
+        })
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::Identifier, "expect class name")?;
+
+        let mut superclass: Option<Rc<Expr>> = None;
+        if self.match_token(&[TokenType::Less]) {
+            let superclass_name = self.consume(TokenType::Identifier, "expect superclass name")?;
+            superclass = Some(Rc::new(Expr::Variable {
+                name: superclass_name,
+                binding: Cell::default(),
+            }));
+        }
+
+        self.consume(TokenType::LeftBrace, "expect '{' before class body")?;
+
+        let mut methods: Vec<Rc<Function>> = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(Rc::new(self.function("method")?));
+        }
+
+        self.consume(TokenType::RightBrace, "expect '}' after class body")?;
+
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: &'static str) -> Result<Function, ParseError> {
         let name = self.consume(
             TokenType::Identifier,
             format!("expect {} name", kind).as_str(),
@@ -58,6 +267,16 @@ impl Parser {
             format!("expect '(' after {} name", kind).as_str(),
         )?;
 
+        let (params, body) = self.function_body(kind)?;
+
+        Ok(Function {
+            name: Some(name),
+            params,
+            body,
+        })
+    }
+
+    fn function_body(&mut self, kind: &'static str) -> Result<(Vec<Token>, Vec<Stmt>), ParseError> {
         let mut params: Vec<Token> = Vec::new();
         if !self.check(TokenType::RightParen) {
             params.push(self.consume(TokenType::Identifier, "expect parameter name.")?);
@@ -75,9 +294,47 @@ impl Parser {
             TokenType::LeftBrace,
             format!("expect '{{' before {} body", kind).as_str(),
         )?;
-        let body = self.block()?;
 
-        Ok(Stmt::FunctionDecl { name, body, params })
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        let body = self.block();
+        self.loop_depth = enclosing_loop_depth;
+
+        Ok((params, body?))
+    }
+
+    fn lambda(&mut self) -> Result<Expr, ParseError> {
+        let pipe = self.previous();
+
+        let mut params: Vec<Token> = Vec::new();
+        if !self.check(TokenType::Pipe) {
+            params.push(self.consume(TokenType::Identifier, "expect parameter name.")?);
+            while self.match_token(&[TokenType::Comma]) {
+                params.push(self.consume(TokenType::Identifier, "expect parameter name.")?);
+            }
+        }
+
+        self.consume(TokenType::Pipe, "expect '|' after lambda parameters")?;
+
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        let body = if self.match_token(&[TokenType::LeftBrace]) {
+            self.block()
+        } else {
+            self.expression().map(|value| {
+                vec![Stmt::Return {
+                    keyword: pipe,
+                    value: Rc::new(value),
+                }]
+            })
+        };
+        self.loop_depth = enclosing_loop_depth;
+
+        Ok(Expr::Lambda(Rc::new(Function {
+            name: None,
+            params,
+            body: body?,
+        })))
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
@@ -98,26 +355,38 @@ impl Parser {
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
         if self.match_token(&[TokenType::If]) {
-            return Ok(self.if_statement()?);
+            return self.if_statement();
         }
 
         if self.match_token(&[TokenType::Return]) {
-            return Ok(self.return_statement()?);
+            return self.return_statement();
         }
 
         if self.match_token(&[TokenType::While]) {
-            return Ok(self.while_statement()?);
+            return self.while_statement();
         }
 
         if self.match_token(&[TokenType::For]) {
-            return Ok(self.for_statement()?);
+            return self.for_statement();
+        }
+
+        if self.match_token(&[TokenType::Break, TokenType::Continue]) {
+            return self.loop_control_statement();
+        }
+
+        if self.match_token(&[TokenType::Throw]) {
+            return self.throw_statement();
+        }
+
+        if self.match_token(&[TokenType::Try]) {
+            return self.try_statement();
         }
 
         if self.match_token(&[TokenType::LeftBrace]) {
             return Ok(Stmt::Block(self.block()?));
         }
 
-        Ok(self.expression_statement()?)
+        self.expression_statement()
     }
 
     fn if_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -154,15 +423,81 @@ impl Parser {
         })
     }
 
+    fn loop_control_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+
+        if self.loop_depth == 0 {
+            return Err(ParseError::new(
+                keyword.to_owned(),
+                format!("'{}' outside of a loop", keyword.lexeme),
+            ));
+        }
+
+        self.consume(
+            TokenType::Semicolon,
+            format!("expect ';' after '{}'", keyword.lexeme).as_str(),
+        )?;
+
+        match keyword.t_type {
+            TokenType::Break => Ok(Stmt::Break(keyword)),
+            _ => Ok(Stmt::Continue(keyword)),
+        }
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+
+        self.consume(TokenType::Semicolon, "expect ';' after thrown value")?;
+
+        Ok(Stmt::Throw {
+            keyword,
+            value: Rc::new(value),
+        })
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftBrace, "expect '{' after 'try'")?;
+        let body = Rc::new(Stmt::Block(self.block()?));
+
+        let mut catch: Option<(Token, Rc<Stmt>)> = None;
+        if self.match_token(&[TokenType::Catch]) {
+            self.consume_optional(TokenType::LeftParen);
+            let name = self.consume(TokenType::Identifier, "expect error name after 'catch'")?;
+            self.consume_optional(TokenType::RightParen);
+
+            self.consume(TokenType::LeftBrace, "expect '{' after catch clause")?;
+            catch = Some((name, Rc::new(Stmt::Block(self.block()?))));
+        }
+
+        let mut finally: Option<Rc<Stmt>> = None;
+        if self.match_token(&[TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace, "expect '{' after 'finally'")?;
+            finally = Some(Rc::new(Stmt::Block(self.block()?)));
+        }
+
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseError::new(
+                self.peak().to_owned(),
+                "expect 'catch' or 'finally' after try block".to_owned(),
+            ));
+        }
+
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
         self.consume_optional(TokenType::LeftParen);
 
-        let stmt: Stmt;
-        if self.check(TokenType::Identifier) {
-            stmt = self.range_for_loop()?;
+        let stmt = if self.check(TokenType::Identifier) {
+            self.range_for_loop()?
         } else {
-            stmt = self.manual_for_loop()?;
-        }
+            self.manual_for_loop()?
+        };
 
         self.consume_optional(TokenType::RightParen);
 
@@ -173,10 +508,20 @@ impl Parser {
         let name = self.advance();
         self.consume(TokenType::In, "expected 'in' after name")?;
         let range_start = self.expression()?;
-        self.consume(TokenType::DotDot, "expected '..' after expression")?;
+
+        if !self.match_token(&[TokenType::DotDot]) {
+            let body = self.loop_body()?;
+
+            return Ok(Stmt::ForIn {
+                name,
+                iterable: Rc::new(range_start),
+                body: Rc::new(body),
+            });
+        }
+
         let range_end = self.expression()?;
 
-        let body = self.statement()?;
+        let body = self.loop_body()?;
 
         Ok(Stmt::ForRange {
             name,
@@ -205,26 +550,23 @@ impl Parser {
 
         self.consume(TokenType::Semicolon, "expect ';' after loop condition")?;
 
-        let mut increment: Option<Expr> = None;
+        let mut increment: Option<Rc<Expr>> = None;
         if !self.check(TokenType::RightParen) {
-            increment = Some(self.expression()?)
+            increment = Some(Rc::new(self.expression()?))
         }
 
         // self.consume(TokenType::RightParen, "expect ')' after 'for'")?;
 
-        let mut body = self.statement()?;
+        let mut body = self.loop_body()?;
 
-        if let Some(increment) = increment {
-            body = Stmt::Block(vec![body, Stmt::Expression(Rc::new(increment))]);
-        }
-
-        if let None = condition {
+        if condition.is_none() {
             condition = Some(Rc::new(Expr::Literal(LiteralType::Bool(true))));
         }
 
         body = Stmt::While {
             condition,
             body: Rc::new(body),
+            increment,
         };
 
         if let Some(initializer) = initializer {
@@ -239,21 +581,30 @@ impl Parser {
         let condition = self.expression()?;
         self.consume_optional(TokenType::RightParen);
 
-        let body = self.statement()?;
+        let body = self.loop_body()?;
 
         Ok(Stmt::While {
             condition: Some(Rc::new(condition)),
             body: Rc::new(body),
+            increment: None,
         })
     }
 
+    fn loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+
+        body
+    }
+
     fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
         let mut statements: Vec<Stmt> = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            let declaration = self.declaration()?;
-
-            statements.push(declaration);
+            if let Some(declaration) = self.recovering_declaration() {
+                statements.push(declaration);
+            }
         }
 
         self.consume(TokenType::RightBrace, "expect '}' after block")?;
@@ -264,7 +615,12 @@ impl Parser {
     fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
         let expr = self.expression()?;
 
-        self.consume(TokenType::Semicolon, "expect ';' after expression")?;
+        // A match ends with a brace like a block, so it reads as a statement on its own.
+        if matches!(expr, Expr::Match { .. }) {
+            self.consume_optional(TokenType::Semicolon);
+        } else if !(self.trailing_expression && self.is_at_end()) {
+            self.consume(TokenType::Semicolon, "expect ';' after expression")?;
+        }
 
         Ok(Stmt::Expression(Rc::new(expr)))
     }
@@ -277,50 +633,84 @@ impl Parser {
         let expr = self.or()?;
 
         if self.match_token(&[TokenType::Equal]) {
-            let name = match expr.as_variable() {
-                Some(name) => name,
-                None => {
-                    return Err(ParseError::new(
-                        self.peak().to_owned(),
-                        "invalid assignment target".to_owned(),
-                    ))
-                }
-            };
-
             let value = self.assignment()?;
 
-            return Ok(Expr::Assign {
-                name: name.to_owned(),
-                value: Rc::new(value),
-            });
+            return match expr {
+                Expr::Variable { name, .. } => Ok(Expr::Assign {
+                    name,
+                    value: Rc::new(value),
+                    binding: Cell::default(),
+                }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    value: Rc::new(value),
+                    operator: None,
+                }),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value: Rc::new(value),
+                    operator: None,
+                }),
+                _ => Err(ParseError::new(
+                    self.peak().to_owned(),
+                    "invalid assignment target".to_owned(),
+                )),
+            };
         } else if self.match_token(&[
             TokenType::PlusEqual,
             TokenType::MinusEqual,
             TokenType::StarEqual,
             TokenType::SlashEqual,
             TokenType::PercentEqual,
+            TokenType::StarStarEqual,
+            TokenType::AmpersandEqual,
+            TokenType::PipeEqual,
+            TokenType::CaretEqual,
+            TokenType::LessLessEqual,
+            TokenType::GreaterGreaterEqual,
         ]) {
-            let name = match expr.as_variable() {
-                Some(name) => name,
-                None => {
-                    return Err(ParseError::new(
-                        self.peak().to_owned(),
-                        "invalid assignment target".to_owned(),
-                    ))
-                }
-            };
-
             let operator = self.previous();
             let value = self.assignment()?;
 
-            return Ok(Expr::Assign {
-                name: name.to_owned(),
-                value: Rc::new(Expr::Binary {
-                    left: Rc::new(Expr::Variable(name.to_owned())),
-                    operator: operator,
-                    right: Rc::new(value),
+            return match &expr {
+                Expr::Variable { name, .. } => Ok(Expr::Assign {
+                    name: name.to_owned(),
+                    value: Rc::new(Expr::Binary {
+                        left: Rc::new(expr.to_owned()),
+                        operator,
+                        right: Rc::new(value),
+                    }),
+                    binding: Cell::default(),
                 }),
-            });
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object: object.to_owned(),
+                    name: name.to_owned(),
+                    value: Rc::new(value),
+                    operator: Some(operator),
+                }),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::SetIndex {
+                    object: object.to_owned(),
+                    bracket: bracket.to_owned(),
+                    index: index.to_owned(),
+                    value: Rc::new(value),
+                    operator: Some(operator),
+                }),
+                _ => Err(ParseError::new(
+                    self.peak().to_owned(),
+                    "invalid assignment target".to_owned(),
+                )),
+            };
         }
 
         Ok(expr)
@@ -378,14 +768,85 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while self.match_token(&[
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
+            TokenType::In,
         ]) {
+            let operator = self.previous();
+            let right = self.bit_or()?;
+
+            expr = Expr::Binary {
+                left: Rc::new(expr),
+                operator,
+                right: Rc::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // A '|' that starts an operand opens a lambda, one that follows an
+    // operand is a bitwise or.
+    fn bit_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_xor()?;
+
+        while self.match_token(&[TokenType::Pipe]) {
+            let operator = self.previous();
+            let right = self.bit_xor()?;
+
+            expr = Expr::Binary {
+                left: Rc::new(expr),
+                operator,
+                right: Rc::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_and()?;
+
+        while self.match_token(&[TokenType::Caret]) {
+            let operator = self.previous();
+            let right = self.bit_and()?;
+
+            expr = Expr::Binary {
+                left: Rc::new(expr),
+                operator,
+                right: Rc::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.shift()?;
+
+        while self.match_token(&[TokenType::Ampersand]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+
+            expr = Expr::Binary {
+                left: Rc::new(expr),
+                operator,
+                right: Rc::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+
+        while self.match_token(&[TokenType::LessLess, TokenType::GreaterGreater]) {
             let operator = self.previous();
             let right = self.term()?;
 
@@ -419,12 +880,7 @@ impl Parser {
     fn factor(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
 
-        while self.match_token(&[
-            TokenType::Slash,
-            TokenType::Star,
-            TokenType::Percent,
-            TokenType::PercentEqual,
-        ]) {
+        while self.match_token(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) {
             let operator = self.previous();
             let right = self.unary()?;
 
@@ -439,7 +895,7 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
+        if self.match_token(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let operator = self.previous();
             let right = self.unary()?;
 
@@ -449,7 +905,26 @@ impl Parser {
             });
         }
 
-        Ok(self.call()?)
+        self.power()
+    }
+
+    // Binds tighter than a unary operator on its left, so -2 ** 2 is -4, and
+    // is right-associative since the exponent is parsed with unary().
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.call()?;
+
+        if self.match_token(&[TokenType::StarStar]) {
+            let operator = self.previous();
+            let right = self.unary()?;
+
+            return Ok(Expr::Binary {
+                left: Rc::new(expr),
+                operator,
+                right: Rc::new(right),
+            });
+        }
+
+        Ok(expr)
     }
 
     fn call(&mut self) -> Result<Expr, ParseError> {
@@ -458,6 +933,22 @@ impl Parser {
         loop {
             if self.match_token(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "expect ']' after index")?;
+
+                expr = Expr::Index {
+                    object: Rc::new(expr),
+                    bracket,
+                    index: Rc::new(index),
+                };
+            } else if self.match_token(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "expect property name after '.'")?;
+
+                expr = Expr::Get {
+                    object: Rc::new(expr),
+                    name,
+                };
             } else {
                 break;
             }
@@ -485,6 +976,37 @@ impl Parser {
         })
     }
 
+    // The scanner splits "a ${b} c" into an Interpolation token holding "a ",
+    // the tokens of b, and a String token holding " c".
+    fn interpolation(&mut self) -> Result<Expr, ParseError> {
+        let mut parts: Vec<Expr> = Vec::new();
+
+        loop {
+            let literal = *self.previous().literal;
+
+            if !matches!(&literal, LiteralType::String(string) if string.is_empty()) {
+                parts.push(Expr::Literal(literal));
+            }
+
+            parts.push(self.expression()?);
+
+            if !self.match_token(&[TokenType::Interpolation]) {
+                break;
+            }
+        }
+
+        let tail = self.consume(
+            TokenType::String,
+            "expect '}' after interpolated expression",
+        )?;
+
+        if !matches!(tail.literal.as_ref(), LiteralType::String(string) if string.is_empty()) {
+            parts.push(Expr::Literal(*tail.literal));
+        }
+
+        Ok(Expr::Interpolation(parts))
+    }
+
     fn primary(&mut self) -> Result<Expr, ParseError> {
         if self.match_token(&[TokenType::False]) {
             return Ok(Expr::Literal(LiteralType::Bool(false)));
@@ -503,8 +1025,95 @@ impl Parser {
             return Ok(Expr::Literal(*value));
         }
 
+        if self.match_token(&[TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+
+        if self.match_token(&[TokenType::Fun]) {
+            self.consume(TokenType::LeftParen, "expect '(' after 'fun'")?;
+            let (params, body) = self.function_body("function")?;
+
+            return Ok(Expr::Lambda(Rc::new(Function {
+                name: None,
+                params,
+                body,
+            })));
+        }
+
+        if self.match_token(&[TokenType::Pipe]) {
+            return self.lambda();
+        }
+
+        if self.match_token(&[TokenType::Match]) {
+            return self.match_expression();
+        }
+
+        if self.match_token(&[TokenType::This]) {
+            return Ok(Expr::This {
+                keyword: self.previous(),
+                binding: Cell::default(),
+            });
+        }
+
+        if self.match_token(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "expect '.' after 'super'")?;
+            let method = self.consume(TokenType::Identifier, "expect superclass method name")?;
+
+            return Ok(Expr::Super {
+                keyword,
+                method,
+                binding: Cell::default(),
+            });
+        }
+
         if self.match_token(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous()));
+            return Ok(Expr::Variable {
+                name: self.previous(),
+                binding: Cell::default(),
+            });
+        }
+
+        if self.match_token(&[TokenType::LeftBracket]) {
+            let mut items: Vec<Expr> = Vec::new();
+
+            if !self.check(TokenType::RightBracket) {
+                items.push(self.expression()?);
+                while self.match_token(&[TokenType::Comma]) {
+                    if self.check(TokenType::RightBracket) {
+                        break;
+                    }
+
+                    items.push(self.expression()?);
+                }
+            }
+
+            self.consume(TokenType::RightBracket, "expect ']' after list items")?;
+
+            return Ok(Expr::List(items));
+        }
+
+        if self.match_token(&[TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut entries: Vec<(Expr, Expr)> = Vec::new();
+
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::Colon, "expect ':' after map key")?;
+                    let value = self.expression()?;
+
+                    entries.push((key, value));
+
+                    if !self.match_token(&[TokenType::Comma]) || self.check(TokenType::RightBrace) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume(TokenType::RightBrace, "expect '}' after map entries")?;
+
+            return Ok(Expr::Map { brace, entries });
         }
 
         if self.match_token(&[TokenType::LeftParen]) {
@@ -521,6 +1130,180 @@ impl Parser {
         ))
     }
 
+    fn match_expression(&mut self) -> Result<Expr, ParseError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+
+        self.consume(TokenType::LeftBrace, "expect '{' after match value")?;
+
+        let mut arms: Vec<MatchArm> = Vec::new();
+        // The arms without a guard, which take every value their pattern matches.
+        let mut unguarded: Vec<(Rc<Pattern>, Span)> = Vec::new();
+
+        while !self.check(TokenType::RightBrace) {
+            let start = self.peak().span.to_owned();
+            let pattern = Rc::new(self.pattern()?);
+            let span = start.to(&self.previous().span);
+
+            let guard = if self.match_token(&[TokenType::If]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+
+            self.consume(TokenType::EqualGreater, "expect '=>' after pattern")?;
+            let body = self.expression()?;
+
+            if let Some((earlier, earlier_span)) = unguarded
+                .iter()
+                .find(|(earlier, _)| covers(earlier, &pattern))
+            {
+                let label = match earlier.as_ref() {
+                    Pattern::Wildcard | Pattern::Binding(_) => "this arm matches every value",
+                    _ => "already matched by this arm",
+                };
+
+                self.warnings.push(
+                    Diagnostic::warning("unreachable match arm", &span)
+                        .with_label(earlier_span, label),
+                );
+            }
+
+            if guard.is_none() {
+                unguarded.push((pattern.clone(), span));
+            }
+
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "expect '}' after match arms")?;
+
+        Ok(Expr::Match {
+            keyword,
+            value: Rc::new(value),
+            arms,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        if self.match_token(&[TokenType::Identifier]) {
+            return Ok(name_pattern(self.previous()));
+        }
+
+        if self.match_token(&[TokenType::LeftBracket]) {
+            return self.list_pattern();
+        }
+
+        if self.match_token(&[TokenType::LeftBrace]) {
+            return self.map_pattern();
+        }
+
+        let start = self.literal_pattern("expect pattern")?;
+
+        if !self.match_token(&[TokenType::DotDot]) {
+            return Ok(Pattern::Literal(start));
+        }
+
+        let dots = self.previous();
+        let end = self.literal_pattern("expect end of range pattern")?;
+
+        let is_number =
+            |value: &LiteralType| matches!(value, LiteralType::Int(_) | LiteralType::Number(_));
+
+        if !is_number(&start) || !is_number(&end) {
+            return Err(ParseError::new(
+                dots,
+                "range patterns can only hold numbers".to_owned(),
+            ));
+        }
+
+        Ok(Pattern::Range(start, end))
+    }
+
+    fn literal_pattern(&mut self, message: &str) -> Result<LiteralType, ParseError> {
+        if self.match_token(&[TokenType::False]) {
+            return Ok(LiteralType::Bool(false));
+        }
+
+        if self.match_token(&[TokenType::True]) {
+            return Ok(LiteralType::Bool(true));
+        }
+
+        if self.match_token(&[TokenType::Nil]) {
+            return Ok(LiteralType::Nil);
+        }
+
+        if self.match_token(&[TokenType::String]) {
+            return Ok(*self.previous().literal);
+        }
+
+        let negative = self.match_token(&[TokenType::Minus]);
+        let number = *self.consume(TokenType::Number, message)?.literal;
+
+        Ok(match number {
+            LiteralType::Int(value) if negative => LiteralType::Int(-value),
+            LiteralType::Number(value) if negative => LiteralType::Number(-value),
+            _ => number,
+        })
+    }
+
+    fn list_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let mut items: Vec<Pattern> = Vec::new();
+        let mut rest: Option<Box<Pattern>> = None;
+
+        while !self.check(TokenType::RightBracket) {
+            if self.match_token(&[TokenType::DotDot]) {
+                rest = Some(Box::new(if self.match_token(&[TokenType::Identifier]) {
+                    name_pattern(self.previous())
+                } else {
+                    Pattern::Wildcard
+                }));
+
+                break;
+            }
+
+            items.push(self.pattern()?);
+
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        let message = match rest {
+            Some(_) => "expect ']' after the rest of a list pattern",
+            None => "expect ']' after list pattern",
+        };
+        self.consume(TokenType::RightBracket, message)?;
+
+        Ok(Pattern::List { items, rest })
+    }
+
+    fn map_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let mut entries: Vec<(LiteralType, Pattern)> = Vec::new();
+
+        while !self.check(TokenType::RightBrace) {
+            let key = self.literal_pattern("expect literal map key")?;
+            self.consume(TokenType::Colon, "expect ':' after map key")?;
+            entries.push((key, self.pattern()?));
+
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "expect '}' after map pattern")?;
+
+        Ok(Pattern::Map(entries))
+    }
+
     fn match_token(&mut self, token_types: &[TokenType]) -> bool {
         for token_type in token_types {
             if self.check(*token_type) {
@@ -540,6 +1323,13 @@ impl Parser {
         self.peak().t_type == token_type
     }
 
+    fn check_next(&self, token_type: TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.t_type == token_type,
+            None => false,
+        }
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -568,9 +1358,69 @@ impl Parser {
         Err(ParseError::new(self.peak().to_owned(), message.to_owned()))
     }
 
+    // 'as' and 'from' only mean something inside an import, so they stay
+    // usable as names everywhere else.
+    fn match_contextual(&mut self, keyword: &str) -> bool {
+        if self.check(TokenType::Identifier) && self.peak().lexeme == keyword {
+            self.advance();
+            return true;
+        }
+
+        false
+    }
+
     fn consume_optional(&mut self, token_type: TokenType) {
         if self.check(token_type) {
             self.advance();
         }
     }
 }
+
+fn name_pattern(name: Token) -> Pattern {
+    if name.lexeme == "_" {
+        Pattern::Wildcard
+    } else {
+        Pattern::Binding(name)
+    }
+}
+
+// Whether every value `later` matches is already matched by `earlier`.
+fn covers(earlier: &Pattern, later: &Pattern) -> bool {
+    match (earlier, later) {
+        (Pattern::Wildcard | Pattern::Binding(_), _) => true,
+        (_, Pattern::Literal(value)) => operators::match_pattern(earlier, value, &mut Vec::new()),
+        (Pattern::Range(..), Pattern::Range(start, end)) => {
+            operators::match_pattern(earlier, start, &mut Vec::new())
+                && operators::match_pattern(earlier, end, &mut Vec::new())
+        }
+        (
+            Pattern::List { items, rest },
+            Pattern::List {
+                items: later_items,
+                rest: later_rest,
+            },
+        ) => {
+            // A rest pattern always binds or ignores what's left, so only the
+            // lengths it allows matter.
+            let lengths = match (rest, later_rest) {
+                (Some(_), _) => later_items.len() >= items.len(),
+                (None, Some(_)) => false,
+                (None, None) => later_items.len() == items.len(),
+            };
+
+            lengths
+                && items
+                    .iter()
+                    .zip(later_items)
+                    .all(|(item, later_item)| covers(item, later_item))
+        }
+        (Pattern::Map(entries), Pattern::Map(later_entries)) => {
+            entries.iter().all(|(key, pattern)| {
+                later_entries.iter().any(|(later_key, later_pattern)| {
+                    key == later_key && covers(pattern, later_pattern)
+                })
+            })
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,108 @@
+use std::{env, path::PathBuf};
+
+use rustyline::{error::ReadlineError, Editor};
+
+use wind_lang::{Interpreter, LiteralType, Source, WindError};
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+pub struct Repl {
+    interpreter: Interpreter,
+    editor: Editor<()>,
+    history_path: Option<PathBuf>,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        let mut editor = Editor::<()>::new();
+        let history_path =
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".wind_history"));
+
+        if let Some(path) = &history_path {
+            let _ = editor.load_history(path);
+        }
+
+        Repl {
+            interpreter: Interpreter::new(),
+            editor,
+            history_path,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let mut buffer = String::new();
+
+        loop {
+            let prompt = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+
+            let line = match self.editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    break;
+                }
+            };
+
+            if buffer.is_empty() && matches!(line.trim(), ":quit" | ":exit") {
+                break;
+            }
+
+            // An empty line submits whatever has been typed so far, even if it is unfinished.
+            let force = !buffer.is_empty() && line.trim().is_empty();
+
+            buffer += &line;
+            buffer.push('\n');
+
+            if buffer.trim().is_empty() {
+                buffer.clear();
+                continue;
+            }
+
+            if self.eval(&buffer, force) {
+                self.editor.add_history_entry(buffer.trim_end());
+                buffer.clear();
+            }
+        }
+
+        if let Some(path) = &self.history_path {
+            let _ = self.editor.save_history(path);
+        }
+    }
+
+    // Returns false when the input is an unfinished declaration and more lines are needed.
+    fn eval(&mut self, source: &str, force: bool) -> bool {
+        let result =
+            wind_lang::parse_repl(Source::new("<repl>", source.to_owned())).and_then(|program| {
+                for warning in program.warnings() {
+                    eprintln!("{}\n", warning);
+                }
+
+                Ok(self.interpreter.interpret_repl(&program)?)
+            });
+
+        match result {
+            Err(e) if e.is_incomplete_input() && !force => false,
+            Err(e) => {
+                e.report();
+                true
+            }
+            Ok(value) => {
+                if let Some(value) = value.filter(|value| !matches!(value, LiteralType::Nil)) {
+                    println!("{}", value.to_repr());
+                }
+
+                true
+            }
+        }
+    }
+}
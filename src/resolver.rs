@@ -0,0 +1,459 @@
+use std::{cell::Cell, rc::Rc};
+
+use fnv::FnvHashMap;
+
+use crate::{
+    ast::{Binding, Expr, Function, Stmt},
+    error::ParseError,
+    token::Token,
+};
+
+#[derive(PartialEq, Clone, Copy)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+struct Local {
+    slot: usize,
+    defined: bool,
+}
+
+pub struct Resolver {
+    scopes: Vec<FnvHashMap<String, Local>>,
+    // How many of the innermost scopes belong to match arms, which are still
+    // inside the initializer of any variable being declared around them.
+    arm_scopes: usize,
+    function_depth: usize,
+    class_kind: ClassKind,
+    errors: Vec<ParseError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Resolver {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            arm_scopes: 0,
+            function_depth: 0,
+            class_kind: ClassKind::None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, statements: &[Stmt]) -> Result<(), Vec<ParseError>> {
+        self.resolve_statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => self.resolve_expr(expr),
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::VarDecl { name, initializer } => {
+                self.declare(name);
+
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+
+                self.define(name);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                if let Some(condition) = condition {
+                    self.resolve_expr(condition);
+                }
+
+                self.resolve_statement(body);
+
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            }
+            Stmt::ForRange {
+                name,
+                range_start,
+                range_end,
+                body,
+            } => {
+                self.resolve_expr(range_start);
+                self.resolve_expr(range_end);
+
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_statement(body);
+                self.end_scope();
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.resolve_expr(iterable);
+
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_statement(body);
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_statement(then_branch);
+
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::FunctionDecl(function) => {
+                let name = function.name.as_ref().unwrap();
+
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(function);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing_class_kind = self.class_kind;
+                self.class_kind = ClassKind::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable {
+                        name: superclass_name,
+                        ..
+                    } = superclass.as_ref()
+                    {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "a class can't inherit from itself");
+                        }
+                    }
+
+                    self.class_kind = ClassKind::Subclass;
+                    self.resolve_expr(superclass);
+
+                    self.begin_scope();
+                    self.define_implicit("super");
+                }
+
+                self.begin_scope();
+                self.define_implicit("this");
+
+                for method in methods {
+                    self.resolve_function(method);
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.class_kind = enclosing_class_kind;
+            }
+            Stmt::Return { keyword, value } => {
+                if self.function_depth == 0 {
+                    self.error(keyword, "can't return from top-level code");
+                }
+
+                self.resolve_expr(value);
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Import {
+                keyword,
+                name,
+                names,
+                ..
+            } => {
+                if !self.scopes.is_empty() {
+                    self.error(keyword, "can't import outside of top-level code");
+                }
+
+                for name in name.iter().chain(names) {
+                    self.declare(name);
+                    self.define(name);
+                }
+            }
+            Stmt::Export {
+                keyword,
+                declaration,
+            } => {
+                if !self.scopes.is_empty() {
+                    self.error(keyword, "can't export outside of top-level code");
+                }
+
+                self.resolve_statement(declaration);
+            }
+            Stmt::Throw { value, .. } => self.resolve_expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.resolve_statement(body);
+
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.declare(name);
+                    self.define(name);
+                    self.resolve_statement(handler);
+                    self.end_scope();
+                }
+
+                if let Some(finally) = finally {
+                    self.resolve_statement(finally);
+                }
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Group(expr) => self.resolve_expr(expr),
+            Expr::Literal(_) => {}
+            Expr::Variable { name, binding } => {
+                let local = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .take(self.arm_scopes + 1)
+                    .find_map(|scope| scope.get(&name.lexeme));
+
+                if let Some(Local { defined: false, .. }) = local {
+                    self.error(
+                        name,
+                        format!(
+                            "can't read local variable '{}' in its own initializer",
+                            name.lexeme
+                        )
+                        .as_str(),
+                    );
+                }
+
+                self.resolve_local(name, binding);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Call { callee, args, .. } => {
+                self.resolve_expr(callee);
+
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Assign {
+                name,
+                value,
+                binding,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_local(name, binding);
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(value);
+            }
+            Expr::List(items) | Expr::Interpolation(items) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            }
+            Expr::Lambda(function) => self.resolve_function(function),
+            Expr::This { keyword, binding } => {
+                if self.class_kind == ClassKind::None {
+                    self.error(keyword, "can't use 'this' outside of a class");
+                    return;
+                }
+
+                self.resolve_local(keyword, binding);
+            }
+            Expr::Super {
+                keyword, binding, ..
+            } => {
+                match self.class_kind {
+                    ClassKind::None => {
+                        self.error(keyword, "can't use 'super' outside of a class");
+                        return;
+                    }
+                    ClassKind::Class => {
+                        self.error(keyword, "can't use 'super' in a class with no superclass");
+                        return;
+                    }
+                    ClassKind::Subclass => {}
+                }
+
+                self.resolve_local(keyword, binding);
+            }
+            Expr::Match { value, arms, .. } => {
+                self.resolve_expr(value);
+
+                for arm in arms {
+                    self.begin_scope();
+                    self.arm_scopes += 1;
+
+                    for name in arm.pattern.bindings() {
+                        self.declare(name);
+                        self.define(name);
+                    }
+
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+
+                    self.resolve_expr(&arm.body);
+
+                    self.arm_scopes -= 1;
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, function: &Rc<Function>) {
+        let enclosing_arm_scopes = self.arm_scopes;
+        self.arm_scopes = 0;
+        self.function_depth += 1;
+        self.begin_scope();
+
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+
+        self.resolve_statements(&function.body);
+
+        self.end_scope();
+        self.function_depth -= 1;
+        self.arm_scopes = enclosing_arm_scopes;
+    }
+
+    fn resolve_local(&mut self, name: &Token, binding: &Cell<Option<Binding>>) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(local) = scope.get(&name.lexeme) {
+                binding.set(Some(Binding {
+                    depth,
+                    slot: local.slot,
+                }));
+
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(FnvHashMap::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let scope = match self.scopes.last_mut() {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        if scope.contains_key(&name.lexeme) {
+            let message = format!("already a variable named '{}' in this scope", name.lexeme);
+            self.error(name, message.as_str());
+
+            return;
+        }
+
+        let slot = scope.len();
+        scope.insert(
+            name.lexeme.to_owned(),
+            Local {
+                slot,
+                defined: false,
+            },
+        );
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.defined = true;
+        }
+    }
+
+    fn define_implicit(&mut self, name: &str) {
+        let scope = self.scopes.last_mut().unwrap();
+
+        scope.insert(
+            name.to_owned(),
+            Local {
+                slot: scope.len(),
+                defined: true,
+            },
+        );
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors
+            .push(ParseError::new(token.to_owned(), message.to_owned()));
+    }
+}
@@ -1,44 +1,65 @@
+use std::rc::Rc;
+
 use crate::{
-    error::{ScannerError, WindError},
-    token::{Token, TokenType},
+    error::ScannerError,
+    token::{Source, Span, Token, TokenType},
     types::LiteralType,
 };
 
 pub struct Scanner {
-    source: String,
+    source: Rc<Source>,
     tokens: Vec<Token>,
 
+    // Byte offsets into the source; lines and columns count characters.
     start: usize,
     current: usize,
     line: i32,
+    column: usize,
+    start_line: i32,
+    start_column: usize,
+
+    // Open braces inside each `${` being scanned, innermost last.
+    interpolations: Vec<usize>,
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Scanner {
+    pub fn new(source: Rc<Source>) -> Scanner {
         Scanner {
             source,
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            interpolations: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<ScannerError>> {
+        let mut errors: Vec<ScannerError> = Vec::new();
+
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
 
-            match self.scan_token() {
-                Err(e) => {
-                    e.report();
-                }
-                _ => (),
+            if let Err(e) = self.scan_token() {
+                errors.push(e);
             }
         }
 
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
         self.add_token(TokenType::EOF, LiteralType::Nil);
 
-        self.tokens.to_owned()
+        Ok(std::mem::take(&mut self.tokens))
     }
 
     fn scan_token(&mut self) -> Result<(), ScannerError> {
@@ -47,9 +68,28 @@ impl Scanner {
         match current_char {
             '(' => self.add_token(TokenType::LeftParen, LiteralType::Nil),
             ')' => self.add_token(TokenType::RightParen, LiteralType::Nil),
-            '{' => self.add_token(TokenType::LeftBrace, LiteralType::Nil),
-            '}' => self.add_token(TokenType::RightBrace, LiteralType::Nil),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+
+                self.add_token(TokenType::LeftBrace, LiteralType::Nil);
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.scan_string()?;
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(TokenType::RightBrace, LiteralType::Nil);
+                }
+                None => self.add_token(TokenType::RightBrace, LiteralType::Nil),
+            },
+            '[' => self.add_token(TokenType::LeftBracket, LiteralType::Nil),
+            ']' => self.add_token(TokenType::RightBracket, LiteralType::Nil),
             ',' => self.add_token(TokenType::Comma, LiteralType::Nil),
+            ':' => self.add_token(TokenType::Colon, LiteralType::Nil),
             '.' => {
                 if self.match_char('.') {
                     self.add_token(TokenType::DotDot, LiteralType::Nil);
@@ -57,6 +97,28 @@ impl Scanner {
                     self.add_token(TokenType::Dot, LiteralType::Nil);
                 }
             }
+            '|' => {
+                if self.match_char('=') {
+                    self.add_token(TokenType::PipeEqual, LiteralType::Nil);
+                } else {
+                    self.add_token(TokenType::Pipe, LiteralType::Nil);
+                }
+            }
+            '&' => {
+                if self.match_char('=') {
+                    self.add_token(TokenType::AmpersandEqual, LiteralType::Nil);
+                } else {
+                    self.add_token(TokenType::Ampersand, LiteralType::Nil);
+                }
+            }
+            '^' => {
+                if self.match_char('=') {
+                    self.add_token(TokenType::CaretEqual, LiteralType::Nil);
+                } else {
+                    self.add_token(TokenType::Caret, LiteralType::Nil);
+                }
+            }
+            '~' => self.add_token(TokenType::Tilde, LiteralType::Nil),
             ';' => self.add_token(TokenType::Semicolon, LiteralType::Nil),
             '-' => {
                 if self.match_char('=') {
@@ -73,7 +135,11 @@ impl Scanner {
                 }
             }
             '*' => {
-                if self.match_char('=') {
+                if self.match_str("*=") {
+                    self.add_token(TokenType::StarStarEqual, LiteralType::Nil);
+                } else if self.match_char('*') {
+                    self.add_token(TokenType::StarStar, LiteralType::Nil);
+                } else if self.match_char('=') {
                     self.add_token(TokenType::StarEqual, LiteralType::Nil);
                 } else {
                     self.add_token(TokenType::Star, LiteralType::Nil);
@@ -107,37 +173,59 @@ impl Scanner {
             '=' => {
                 if self.match_char('=') {
                     self.add_token(TokenType::EqualEqual, LiteralType::Nil);
+                } else if self.match_char('>') {
+                    self.add_token(TokenType::EqualGreater, LiteralType::Nil);
                 } else {
                     self.add_token(TokenType::Equal, LiteralType::Nil);
                 }
             }
             '<' => {
-                if self.match_char('=') {
+                if self.match_str("<=") {
+                    self.add_token(TokenType::LessLessEqual, LiteralType::Nil);
+                } else if self.match_char('<') {
+                    self.add_token(TokenType::LessLess, LiteralType::Nil);
+                } else if self.match_char('=') {
                     self.add_token(TokenType::LessEqual, LiteralType::Nil);
                 } else {
                     self.add_token(TokenType::Less, LiteralType::Nil);
                 }
             }
             '>' => {
-                if self.match_char('=') {
+                if self.match_str(">=") {
+                    self.add_token(TokenType::GreaterGreaterEqual, LiteralType::Nil);
+                } else if self.match_char('>') {
+                    self.add_token(TokenType::GreaterGreater, LiteralType::Nil);
+                } else if self.match_char('=') {
                     self.add_token(TokenType::GreaterEqual, LiteralType::Nil);
                 } else {
                     self.add_token(TokenType::Greater, LiteralType::Nil);
                 }
             }
-            '"' => self.scan_string()?,
-            ' ' | '\r' | '\t' => {}
-            '\n' => {
-                self.line += 1;
+            '"' => {
+                if self.match_str("\"\"") {
+                    self.scan_verbatim_string("\"\"\"")?;
+                } else {
+                    self.scan_string()?;
+                }
             }
+            ' ' | '\r' | '\t' => {}
+            '\n' => self.new_line(),
             _ => {
-                if current_char.is_numeric() {
-                    self.scan_number()?;
-                } else if current_char.is_alphabetic() {
+                if current_char == 'r' && self.peak() == '"' {
+                    self.advance();
+
+                    if self.match_str("\"\"") {
+                        self.scan_verbatim_string("\"\"\"")?;
+                    } else {
+                        self.scan_verbatim_string("\"")?;
+                    }
+                } else if current_char.is_ascii_digit() {
+                    self.scan_number(current_char)?;
+                } else if current_char.is_alphabetic() || current_char == '_' {
                     self.scan_identifier();
                 } else {
                     return Err(ScannerError::new(
-                        self.line,
+                        self.span(),
                         format!("unexpected character '{}'", current_char),
                     ));
                 }
@@ -148,114 +236,433 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> char {
-        let current_char = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let current_char = self.peak();
+        self.current += current_char.len_utf8();
+        self.column += 1;
 
         current_char
     }
 
     fn scan_string(&mut self) -> Result<(), ScannerError> {
+        let mut string = String::new();
+        let mut error = None;
+
         while !self.is_at_end() && self.peak() != '"' {
-            if self.peak() == '\n' {
-                self.line += 1;
-            }
+            match self.advance() {
+                '\\' => match self.scan_escape() {
+                    Ok(c) => string.push(c),
+                    Err(e) => {
+                        // Keep going so the rest of the string isn't scanned as code.
+                        error.get_or_insert(e);
+                    }
+                },
+                '\n' => {
+                    self.new_line();
+                    string.push('\n');
+                }
+                '$' if self.match_char('{') => {
+                    // The expression is scanned as ordinary tokens until its
+                    // closing brace, which resumes the string.
+                    self.interpolations.push(0);
 
-            self.advance();
+                    if let Some(e) = error {
+                        return Err(e);
+                    }
+
+                    self.add_token(TokenType::Interpolation, LiteralType::String(string));
+
+                    return Ok(());
+                }
+                c => string.push(c),
+            }
         }
 
         if self.is_at_end() {
             return Err(ScannerError::new(
-                self.line,
+                self.span(),
                 "unterminated string".to_owned(),
             ));
         }
 
         self.advance(); // "
 
-        let string = self.source[self.start + 1..self.current - 1]
-            .to_owned()
-            .replace("\\n", "\n");
+        if let Some(e) = error {
+            return Err(e);
+        }
 
         self.add_token(TokenType::String, LiteralType::String(string));
 
         Ok(())
     }
 
-    fn scan_number(&mut self) -> Result<(), ScannerError> {
-        while self.peak().is_numeric() {
-            self.advance();
+    fn scan_escape(&mut self) -> Result<char, ScannerError> {
+        // The backslash has already been consumed.
+        let start = self.current - 1;
+        let line = self.line;
+        let column = self.column - 1;
+
+        // Leave an unterminated string for scan_string to report.
+        if self.is_at_end() {
+            return Ok('\\');
         }
 
-        if self.peak() == '.' && self.peak_next().is_numeric() {
+        let c = self.advance();
+
+        let escaped = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            '0' => '\0',
+            'u' => return self.scan_unicode_escape(start, line, column),
+            _ => {
+                if c == '\n' {
+                    self.new_line();
+                }
+
+                return Err(ScannerError::new(
+                    self.span_from(start, line, column),
+                    format!("unknown escape sequence '\\{}'", c.escape_default()),
+                ));
+            }
+        };
+
+        Ok(escaped)
+    }
+
+    fn scan_unicode_escape(
+        &mut self,
+        start: usize,
+        line: i32,
+        column: usize,
+    ) -> Result<char, ScannerError> {
+        if !self.match_char('{') {
+            return Err(ScannerError::new(
+                self.span_from(start, line, column),
+                "expected '{' after '\\u'".to_owned(),
+            ));
+        }
+
+        let digits_start = self.current;
+
+        while self.peak().is_ascii_hexdigit() {
             self.advance();
+        }
+
+        let digits = self.source.code[digits_start..self.current].to_owned();
+
+        if digits.is_empty() || digits.len() > 6 || !self.match_char('}') {
+            return Err(ScannerError::new(
+                self.span_from(start, line, column),
+                "unicode escape must be '\\u{' followed by 1 to 6 hex digits and '}'".to_owned(),
+            ));
+        }
 
-            while self.peak().is_numeric() {
+        let value = u32::from_str_radix(&digits, 16).unwrap();
+
+        char::from_u32(value).ok_or_else(|| {
+            ScannerError::new(
+                self.span_from(start, line, column),
+                format!("invalid unicode escape '\\u{{{}}}'", digits),
+            )
+        })
+    }
+
+    // Raw and triple-quoted strings keep everything between the delimiters
+    // as written, escapes and newlines included.
+    fn scan_verbatim_string(&mut self, delimiter: &str) -> Result<(), ScannerError> {
+        let content_start = self.current;
+
+        while !self.is_at_end() && !self.source.code[self.current..].starts_with(delimiter) {
+            if self.advance() == '\n' {
+                self.new_line();
+            }
+        }
+
+        if self.is_at_end() {
+            return Err(ScannerError::new(
+                self.span(),
+                "unterminated string".to_owned(),
+            ));
+        }
+
+        let string = self.source.code[content_start..self.current].to_owned();
+        self.match_str(delimiter);
+
+        self.add_token(TokenType::String, LiteralType::String(string));
+
+        Ok(())
+    }
+
+    fn scan_number(&mut self, first_digit: char) -> Result<(), ScannerError> {
+        if first_digit == '0' {
+            let radix = match self.peak() {
+                'x' => Some((16, "hexadecimal")),
+                'o' => Some((8, "octal")),
+                'b' => Some((2, "binary")),
+                _ => None,
+            };
+
+            if let Some((radix, name)) = radix {
                 self.advance();
+
+                return self.scan_radix_number(radix, name);
             }
         }
 
-        let literal = self.source[self.start..self.current].to_owned();
-        let float: f32 = match literal.parse() {
-            Ok(v) => v,
-            Err(_) => {
+        self.scan_digits();
+
+        let mut is_float = false;
+
+        if self.peak() == '.' && self.peak_next().is_ascii_digit() {
+            is_float = true;
+            self.advance();
+            self.scan_digits();
+        } else if self.peak() == '.' && matches!(self.peak_next(), 'e' | 'E' | '_') {
+            self.advance();
+            self.skip_malformed_number();
+
+            return Err(ScannerError::new(
+                self.span(),
+                "expected digits after the decimal point".to_owned(),
+            ));
+        }
+
+        let mut has_exponent = false;
+
+        if self.peak() == 'e' || self.peak() == 'E' {
+            is_float = true;
+            has_exponent = true;
+            self.advance();
+
+            if self.peak() == '+' || self.peak() == '-' {
+                self.advance();
+            }
+
+            if !self.peak().is_ascii_digit() {
                 return Err(ScannerError::new(
-                    self.line,
-                    "cannot parse number".to_owned(),
+                    self.span(),
+                    "expected digits after the exponent".to_owned(),
                 ));
             }
+
+            self.scan_digits();
+        }
+
+        if self.peak() == '.' && self.peak_next().is_ascii_digit() {
+            self.advance();
+            self.skip_malformed_number();
+
+            let message = if has_exponent {
+                "the exponent of a number must be an integer"
+            } else {
+                "a number can only have one decimal point"
+            };
+
+            return Err(ScannerError::new(self.span(), message.to_owned()));
+        }
+
+        let literal = &self.source.code[self.start..self.current];
+        self.check_separators(literal, 10)?;
+
+        let digits: String = literal.chars().filter(|c| *c != '_').collect();
+
+        let value = if is_float {
+            // The checks above only let through digits with an optional
+            // fraction and exponent, which always parse; a huge exponent
+            // gives infinity rather than an error.
+            match digits.parse() {
+                Ok(value) => LiteralType::Number(value),
+                Err(_) => unreachable!(),
+            }
+        } else {
+            match digits.parse() {
+                Ok(value) => LiteralType::Int(value),
+                Err(_) => return Err(self.too_large_error()),
+            }
         };
 
-        self.add_token(TokenType::Number, LiteralType::Number(float));
+        self.add_token(TokenType::Number, value);
+
+        Ok(())
+    }
+
+    // 0x, 0o and 0b literals; the prefix has already been consumed.
+    fn scan_radix_number(&mut self, radix: u32, name: &str) -> Result<(), ScannerError> {
+        // Take every alphanumeric character so a bad digit is reported as part
+        // of the literal instead of starting an identifier.
+        while self.peak().is_ascii_alphanumeric() || self.peak() == '_' {
+            self.advance();
+        }
+
+        if self.peak() == '.' && self.peak_next().is_ascii_digit() {
+            self.advance();
+            self.skip_malformed_number();
+
+            return Err(ScannerError::new(
+                self.span(),
+                format!("{} literals can't have a fractional part", name),
+            ));
+        }
+
+        let literal = &self.source.code[self.start..self.current];
+        let digits: String = literal[2..].chars().filter(|c| *c != '_').collect();
+
+        if digits.is_empty() {
+            return Err(ScannerError::new(
+                self.span(),
+                format!("missing digits after '{}'", &literal[..2]),
+            ));
+        }
+
+        if let Some(digit) = digits.chars().find(|c| !c.is_digit(radix)) {
+            return Err(ScannerError::new(
+                self.span(),
+                format!("invalid digit '{}' in {} literal", digit, name),
+            ));
+        }
+
+        self.check_separators(&literal[2..], radix)?;
+
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) => {
+                self.add_token(TokenType::Number, LiteralType::Int(value));
+
+                Ok(())
+            }
+            Err(_) => Err(self.too_large_error()),
+        }
+    }
+
+    fn scan_digits(&mut self) {
+        while self.peak().is_ascii_digit() || self.peak() == '_' {
+            self.advance();
+        }
+    }
+
+    // Takes the rest of a literal that can't be a number, so the error covers
+    // all of it and scanning picks up after it.
+    fn skip_malformed_number(&mut self) {
+        while self.peak().is_ascii_alphanumeric()
+            || self.peak() == '_'
+            || (self.peak() == '.' && self.peak_next().is_ascii_digit())
+        {
+            self.advance();
+        }
+    }
+
+    fn check_separators(&self, literal: &str, radix: u32) -> Result<(), ScannerError> {
+        let chars: Vec<char> = literal.chars().collect();
+
+        for (index, c) in chars.iter().enumerate() {
+            if *c != '_' {
+                continue;
+            }
+
+            let after_digit = index > 0 && chars[index - 1].is_digit(radix);
+            let before_digit = chars.get(index + 1).is_some_and(|c| c.is_digit(radix));
+
+            if !after_digit || !before_digit {
+                return Err(ScannerError::new(
+                    self.span(),
+                    "'_' can only be used between digits".to_owned(),
+                ));
+            }
+        }
 
         Ok(())
     }
 
+    fn too_large_error(&self) -> ScannerError {
+        ScannerError::new(
+            self.span(),
+            format!("integer literal is too large, the maximum is {}", i64::MAX),
+        )
+    }
+
     fn scan_identifier(&mut self) {
         while self.peak().is_alphanumeric() || self.peak() == '_' {
             self.advance();
         }
 
-        let text = self.source[self.start..self.current].to_owned();
+        let text = self.source.code[self.start..self.current].to_owned();
         let token_type = self.match_keyword(&text);
 
         self.add_token(token_type, LiteralType::Nil);
     }
 
     fn add_token(&mut self, t_type: TokenType, literal: LiteralType) {
-        let text = self.source[self.start..self.current].to_owned();
+        let text = self.source.code[self.start..self.current].to_owned();
 
         self.tokens
-            .push(Token::new(t_type, text, Box::new(literal), self.line));
+            .push(Token::new(t_type, text, Box::new(literal), self.span()));
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            source: self.source.clone(),
+            start: self.start,
+            end: self.current,
+            line: self.start_line,
+            column: self.start_column,
+        }
+    }
+
+    fn span_from(&self, start: usize, line: i32, column: usize) -> Span {
+        Span {
+            source: self.source.clone(),
+            start,
+            end: self.current,
+            line,
+            column,
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.current >= self.source.code.len()
     }
 
     fn match_char(&mut self, expected: char) -> bool {
         if self.is_at_end() || self.peak() != expected {
-            return false;
+            false
         } else {
             self.advance();
 
-            return true;
+            true
         }
     }
 
-    fn peak(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
+    fn match_str(&mut self, expected: &str) -> bool {
+        if !self.source.code[self.current..].starts_with(expected) {
+            return false;
+        }
+
+        for _ in expected.chars() {
+            self.advance();
         }
 
-        self.source.chars().nth(self.current).unwrap()
+        true
+    }
+
+    fn peak(&self) -> char {
+        self.source.code[self.current..]
+            .chars()
+            .next()
+            .unwrap_or('\0')
     }
 
     fn peak_next(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
+        let mut chars = self.source.code[self.current..].chars();
+        chars.next();
 
-        self.source.chars().nth(self.current + 1).unwrap()
+        chars.next().unwrap_or('\0')
     }
 
     fn match_keyword(&mut self, name: &str) -> TokenType {
@@ -276,6 +683,15 @@ impl Scanner {
             "this" => TokenType::This,
             "var" => TokenType::Var,
             "in" => TokenType::In,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "import" => TokenType::Import,
+            "export" => TokenType::Export,
+            "throw" => TokenType::Throw,
+            "try" => TokenType::Try,
+            "catch" => TokenType::Catch,
+            "finally" => TokenType::Finally,
+            "match" => TokenType::Match,
             _ => TokenType::Identifier,
         }
     }
@@ -1,12 +1,18 @@
+use std::{fmt::Debug, rc::Rc};
+
 use crate::types::LiteralType;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     DotDot,
     Minus,
@@ -14,24 +20,39 @@ pub enum TokenType {
     Plus,
     PlusEqual,
     Percent,
+    Pipe,
     PercentEqual,
     Semicolon,
     Slash,
     SlashEqual,
     Star,
     StarEqual,
+    StarStar,
+    StarStarEqual,
+    Ampersand,
+    AmpersandEqual,
+    PipeEqual,
+    Caret,
+    CaretEqual,
+    Tilde,
 
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    EqualGreater,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    LessLessEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
 
     Identifier,
     String,
+    Interpolation,
     Number,
 
     And,
@@ -50,25 +71,79 @@ pub enum TokenType {
     Var,
     While,
     In,
+    Break,
+    Continue,
+    Import,
+    Export,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Match,
 
     EOF,
 }
 
+pub struct Source {
+    pub name: String,
+    pub code: String,
+}
+
+impl Source {
+    pub fn new(name: &str, code: String) -> Rc<Source> {
+        Rc::new(Source {
+            name: name.to_owned(),
+            code,
+        })
+    }
+}
+
 #[derive(Clone)]
+pub struct Span {
+    pub source: Rc<Source>,
+    pub start: usize,
+    pub end: usize,
+    pub line: i32,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            source: self.source.clone(),
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{} ({}..{})",
+            self.source.name, self.line, self.column, self.start, self.end
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     pub t_type: TokenType,
     pub lexeme: String,
     pub literal: Box<LiteralType>,
-    pub line: i32,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(t_type: TokenType, lexeme: String, literal: Box<LiteralType>, line: i32) -> Token {
+    pub fn new(t_type: TokenType, lexeme: String, literal: Box<LiteralType>, span: Span) -> Token {
         Token {
             t_type,
             lexeme,
             literal,
-            line,
+            span,
         }
     }
 }
@@ -1,26 +1,208 @@
-use std::{fmt::Display, rc::Rc};
+use std::{cell::RefCell, convert::TryFrom, fmt::Display, rc::Rc};
+
+use fnv::FnvHashMap;
 
 use crate::{
-    ast::Stmt,
+    ast::Function,
     error::RuntimeError,
-    interpreter::{environment::Environment, Interpreter},
-    token::Token,
+    interpreter::{environment::Environment, Flow, Interpreter},
+    module::Module,
+    token::{Span, Token},
+    vm::{Closure, Globals},
 };
 
 #[derive(Clone)]
 pub enum LiteralType {
     Nil,
-    Number(f32),
+    Int(i64),
+    Number(f64),
     String(String),
     Bool(bool),
     Function {
-        deceleration: Stmt,
+        deceleration: Rc<Function>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
     },
     NativeFunction {
         name: String,
         arity: usize,
         func: Rc<dyn Fn(Vec<LiteralType>, Token) -> Result<LiteralType, RuntimeError>>,
     },
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<Vec<LiteralType>>>),
+    Map(Rc<RefCell<Map>>),
+    Module(Rc<Namespace>),
+}
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: FnvHashMap<String, LiteralType>,
+}
+
+impl Class {
+    pub fn find_method(&self, name: &str) -> Option<LiteralType> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method.to_owned());
+        }
+
+        match &self.superclass {
+            Some(superclass) => superclass.find_method(name),
+            None => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Int(i64),
+    Number(u64),
+    String(String),
+    Bool(bool),
+}
+
+#[derive(Default)]
+pub struct Map {
+    entries: Vec<(LiteralType, LiteralType)>,
+    indices: FnvHashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn get(&self, key: &MapKey) -> Option<&LiteralType> {
+        self.indices.get(key).map(|index| &self.entries[*index].1)
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: MapKey, key_value: LiteralType, value: LiteralType) {
+        match self.indices.get(&key) {
+            Some(index) => self.entries[*index].1 = value,
+            None => {
+                self.indices.insert(key, self.entries.len());
+                self.entries.push((key_value, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<LiteralType> {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(index);
+
+        for entry_index in self.indices.values_mut() {
+            if *entry_index > index {
+                *entry_index -= 1;
+            }
+        }
+
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn keys(&self) -> Vec<LiteralType> {
+        self.entries.iter().map(|(key, _)| key.to_owned()).collect()
+    }
+
+    pub fn values(&self) -> Vec<LiteralType> {
+        self.entries
+            .iter()
+            .map(|(_, value)| value.to_owned())
+            .collect()
+    }
+}
+
+// Entry order doesn't matter, only that the same keys hold equal values.
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+impl Map {
+    fn equals(&self, other: &Map, visited: &mut Visited) -> bool {
+        self.len() == other.len()
+            && self.indices.iter().all(|(key, index)| {
+                other
+                    .get(key)
+                    .is_some_and(|value| self.entries[*index].1.equals(value, visited))
+            })
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: FnvHashMap<String, LiteralType>,
+}
+
+impl Instance {
+    pub fn get(
+        instance: &Rc<RefCell<Instance>>,
+        name: &Token,
+    ) -> Result<LiteralType, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.to_owned());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme);
+
+        match method {
+            Some(method) => Ok(method.bind(LiteralType::Instance(instance.clone()))),
+            None => Err(RuntimeError::new(
+                name.to_owned(),
+                format!("undefined property '{}'", name.lexeme),
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: LiteralType) {
+        self.fields.insert(name.lexeme.to_owned(), value);
+    }
+}
+
+// Where a module's globals live, which depends on the backend that ran it.
+pub enum ModuleGlobals {
+    Environment(Rc<RefCell<Environment>>),
+    Table(Globals),
+}
+
+// What importing a module gives back. Exports are read from the module's
+// globals every time, so they see whatever the module assigned since.
+pub struct Namespace {
+    pub module: Rc<Module>,
+    pub globals: ModuleGlobals,
+}
+
+impl Namespace {
+    pub fn get(&self, name: &Token) -> Result<LiteralType, RuntimeError> {
+        if !self.module.exports.contains(&name.lexeme) {
+            return Err(RuntimeError::new(
+                name.to_owned(),
+                format!(
+                    "module '{}' has no export named '{}'",
+                    self.module.name, name.lexeme
+                ),
+            ));
+        }
+
+        // Running the module defined every name it exports.
+        Ok(match &self.globals {
+            ModuleGlobals::Environment(environment) => {
+                environment.borrow().values[&name.lexeme].to_owned()
+            }
+            ModuleGlobals::Table(globals) => globals.borrow()[&name.lexeme].to_owned(),
+        })
+    }
 }
 
 impl Display for LiteralType {
@@ -30,25 +212,275 @@ impl Display for LiteralType {
             "{}",
             match self {
                 LiteralType::Nil => "nil".to_owned(),
+                LiteralType::Int(int_value) => format!("{}", int_value),
+                // Whole floats keep their ".0" so they don't read as ints.
+                LiteralType::Number(number_value) if number_value.fract() == 0.0 => {
+                    format!("{:.1}", number_value)
+                }
                 LiteralType::Number(number_value) => format!("{}", number_value),
-                LiteralType::String(string_value) => format!("{}", string_value),
+                LiteralType::String(string_value) => string_value.to_string(),
                 LiteralType::Bool(bool_value) => format!("{}", bool_value),
-                LiteralType::Function { deceleration } => {
-                    let (name, _, _) = deceleration.as_function_decl().unwrap();
-
-                    format!("<fn {}>", name.lexeme)
-                }
+                LiteralType::Function { deceleration, .. } => match &deceleration.name {
+                    Some(name) => format!("<fn {}>", name.lexeme),
+                    None => "<fn anonymous>".to_owned(),
+                },
                 LiteralType::NativeFunction {
                     name,
                     arity: _,
                     func: _,
                 } => format!("<fn {}>", name),
+                LiteralType::Closure(closure) => match closure.name() {
+                    Some(name) => format!("<fn {}>", name),
+                    None => "<fn anonymous>".to_owned(),
+                },
+                LiteralType::Class(class) => format!("<class {}>", class.name),
+                LiteralType::Instance(instance) => {
+                    format!("<{} instance>", instance.borrow().class.name)
+                }
+                LiteralType::List(_) | LiteralType::Map(_) => self.to_repr_within(&mut Vec::new()),
+                LiteralType::Module(namespace) => format!("<module {}>", namespace.module.name),
             }
         )
     }
 }
 
+// Values compare by content, functions, classes and modules by identity.
+// Values of different types are never equal, except ints and floats holding
+// the same number.
+impl PartialEq for LiteralType {
+    fn eq(&self, other: &LiteralType) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+// The pairs of lists, maps or instances being compared around the current
+// values.
+type Visited = Vec<(*const (), *const ())>;
+
+impl LiteralType {
+    // Containers can hold themselves, so comparing them can come back to a
+    // pair that is already being compared. Any difference between those is
+    // found where they were first met, so the pair counts as equal here.
+    fn equals(&self, other: &LiteralType, visited: &mut Visited) -> bool {
+        match (self, other) {
+            (LiteralType::Nil, LiteralType::Nil) => true,
+            (LiteralType::Int(left), LiteralType::Int(right)) => left == right,
+            (LiteralType::Number(left), LiteralType::Number(right)) => left == right,
+            (LiteralType::Int(left), LiteralType::Number(right))
+            | (LiteralType::Number(right), LiteralType::Int(left)) => *left as f64 == *right,
+            (LiteralType::String(left), LiteralType::String(right)) => left == right,
+            (LiteralType::Bool(left), LiteralType::Bool(right)) => left == right,
+            (
+                LiteralType::Function {
+                    deceleration: left_deceleration,
+                    closure: left_closure,
+                    ..
+                },
+                LiteralType::Function {
+                    deceleration: right_deceleration,
+                    closure: right_closure,
+                    ..
+                },
+            ) => {
+                Rc::ptr_eq(left_deceleration, right_deceleration)
+                    && Rc::ptr_eq(left_closure, right_closure)
+            }
+            (
+                LiteralType::NativeFunction { func: left, .. },
+                LiteralType::NativeFunction { func: right, .. },
+            ) => Rc::ptr_eq(left, right),
+            (LiteralType::Closure(left), LiteralType::Closure(right)) => Rc::ptr_eq(left, right),
+            (LiteralType::Class(left), LiteralType::Class(right)) => Rc::ptr_eq(left, right),
+            (LiteralType::Instance(left), LiteralType::Instance(right)) => {
+                within(visited, left, right, |visited| {
+                    let (left, right) = (left.borrow(), right.borrow());
+
+                    Rc::ptr_eq(&left.class, &right.class)
+                        && left.fields.len() == right.fields.len()
+                        && left.fields.iter().all(|(name, value)| {
+                            right
+                                .fields
+                                .get(name)
+                                .is_some_and(|other| value.equals(other, visited))
+                        })
+                })
+            }
+            (LiteralType::List(left), LiteralType::List(right)) => {
+                within(visited, left, right, |visited| {
+                    let (left, right) = (left.borrow(), right.borrow());
+
+                    left.len() == right.len()
+                        && left
+                            .iter()
+                            .zip(right.iter())
+                            .all(|(left, right)| left.equals(right, visited))
+                })
+            }
+            (LiteralType::Map(left), LiteralType::Map(right)) => {
+                within(visited, left, right, |visited| {
+                    left.borrow().equals(&right.borrow(), visited)
+                })
+            }
+            (LiteralType::Module(left), LiteralType::Module(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+fn within<T>(
+    visited: &mut Visited,
+    left: &Rc<T>,
+    right: &Rc<T>,
+    compare: impl FnOnce(&mut Visited) -> bool,
+) -> bool {
+    let pair = (
+        Rc::as_ptr(left) as *const (),
+        Rc::as_ptr(right) as *const (),
+    );
+
+    if Rc::ptr_eq(left, right) || visited.contains(&pair) {
+        return true;
+    }
+
+    visited.push(pair);
+    let equal = compare(visited);
+    visited.pop();
+
+    equal
+}
+
+impl std::fmt::Debug for LiteralType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_repr())
+    }
+}
+
 impl LiteralType {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LiteralType::Nil => "nil",
+            LiteralType::Int(_) => "int",
+            LiteralType::Number(_) => "number",
+            LiteralType::String(_) => "string",
+            LiteralType::Bool(_) => "bool",
+            LiteralType::Function { .. }
+            | LiteralType::NativeFunction { .. }
+            | LiteralType::Closure(_) => "function",
+            LiteralType::Class(_) => "class",
+            LiteralType::Instance(_) => "instance",
+            LiteralType::List(_) => "list",
+            LiteralType::Map(_) => "map",
+            LiteralType::Module(_) => "module",
+        }
+    }
+
+    pub fn to_repr(&self) -> String {
+        match self {
+            LiteralType::String(string_value) => format!("\"{}\"", string_value),
+            _ => format!("{}", self),
+        }
+    }
+
+    // Like `to_repr`, but `parents` holds the lists and maps being printed
+    // around this value, so one that contains itself prints as `[...]` or
+    // `{...}` instead of recursing forever.
+    fn to_repr_within(&self, parents: &mut Vec<*const ()>) -> String {
+        match self {
+            LiteralType::List(items) => {
+                let pointer = Rc::as_ptr(items) as *const ();
+
+                if parents.contains(&pointer) {
+                    return "[...]".to_owned();
+                }
+
+                parents.push(pointer);
+
+                let items: Vec<String> = items
+                    .borrow()
+                    .iter()
+                    .map(|item| item.to_repr_within(parents))
+                    .collect();
+
+                parents.pop();
+
+                format!("[{}]", items.join(", "))
+            }
+            LiteralType::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+
+                if parents.contains(&pointer) {
+                    return "{...}".to_owned();
+                }
+
+                parents.push(pointer);
+
+                let entries: Vec<String> = map
+                    .borrow()
+                    .entries
+                    .iter()
+                    .map(|(key, value)| {
+                        format!("{}: {}", key.to_repr(), value.to_repr_within(parents))
+                    })
+                    .collect();
+
+                parents.pop();
+
+                format!("{{{}}}", entries.join(", "))
+            }
+            _ => self.to_repr(),
+        }
+    }
+
+    pub fn to_key(&self, token: &Token) -> Result<MapKey, RuntimeError> {
+        self.as_key().ok_or_else(|| {
+            RuntimeError::new(
+                token.to_owned(),
+                "map keys must be strings, numbers, booleans or nil".to_owned(),
+            )
+        })
+    }
+
+    pub fn as_key(&self) -> Option<MapKey> {
+        match self {
+            LiteralType::Nil => Some(MapKey::Nil),
+            LiteralType::Int(value) => Some(MapKey::Int(*value)),
+            // Floats compare equal to the ints they hold (and -0.0 to 0), so
+            // they have to hash to the same key.
+            LiteralType::Number(value)
+                if value.fract() == 0.0
+                    && *value >= i64::MIN as f64
+                    && *value < i64::MAX as f64 =>
+            {
+                Some(MapKey::Int(*value as i64))
+            }
+            LiteralType::Number(value) => Some(MapKey::Number(value.to_bits())),
+            LiteralType::String(value) => Some(MapKey::String(value.to_owned())),
+            LiteralType::Bool(value) => Some(MapKey::Bool(*value)),
+            _ => None,
+        }
+    }
+
+    pub fn to_index(&self, len: usize, token: &Token) -> Result<usize, RuntimeError> {
+        match self {
+            LiteralType::Int(value) if *value >= 0 => {
+                let index = *value as usize;
+
+                if index < len {
+                    Ok(index)
+                } else {
+                    Err(RuntimeError::new(
+                        token.to_owned(),
+                        format!("index {} out of bounds for length {}", index, len),
+                    ))
+                }
+            }
+            _ => Err(RuntimeError::new(
+                token.to_owned(),
+                "index must be a non-negative integer".to_owned(),
+            )),
+        }
+    }
+
     pub fn call(
         &self,
         interpreter: &mut Interpreter,
@@ -56,9 +488,13 @@ impl LiteralType {
         args: Vec<LiteralType>,
     ) -> Result<LiteralType, RuntimeError> {
         match self {
-            LiteralType::Function { deceleration } => {
-                let (_, params, body) = deceleration.as_function_decl().unwrap();
-                let environment = Environment::with_enclosing(interpreter.environment.clone());
+            LiteralType::Function {
+                deceleration,
+                closure,
+                is_initializer,
+            } => {
+                let params = &deceleration.params;
+                let environment = Environment::with_enclosing(closure.clone());
 
                 for i in 0..params.len() {
                     environment
@@ -66,20 +502,38 @@ impl LiteralType {
                         .define(params[i].lexeme.to_owned(), args[i].to_owned());
                 }
 
-                match interpreter.execute_block(&body, environment)? {
-                    Some(value) => {
-                        return Ok(value);
+                let flow = interpreter.execute_block(&deceleration.body, environment)?;
+
+                if *is_initializer {
+                    if let Some(this) = closure.borrow().slots.first() {
+                        return Ok(this.to_owned());
                     }
-                    None => (),
                 }
 
-                Ok(LiteralType::Nil)
+                match flow {
+                    Some(Flow::Return(value)) => Ok(value),
+                    _ => Ok(LiteralType::Nil),
+                }
             }
             LiteralType::NativeFunction {
                 name: _,
                 arity: _,
                 func,
             } => func(args, paren.to_owned()),
+            LiteralType::Class(class) => {
+                let instance = LiteralType::Instance(Rc::new(RefCell::new(Instance {
+                    class: class.clone(),
+                    fields: FnvHashMap::default(),
+                })));
+
+                if let Some(initializer) = class.find_method("init") {
+                    initializer
+                        .bind(instance.to_owned())
+                        .call(interpreter, paren, args)?;
+                }
+
+                Ok(instance)
+            }
             _ => Err(RuntimeError::new(
                 paren.to_owned(),
                 "can only call functions and classes".to_owned(),
@@ -89,20 +543,227 @@ impl LiteralType {
 
     pub fn arity(&self, paren: &Token) -> Result<usize, RuntimeError> {
         match self {
-            LiteralType::Function { deceleration } => {
-                let (_, params, _) = deceleration.as_function_decl().unwrap();
-
-                Ok(params.len())
-            }
+            LiteralType::Function { deceleration, .. } => Ok(deceleration.params.len()),
             LiteralType::NativeFunction {
                 name: _,
                 arity,
                 func: _,
             } => Ok(*arity),
+            LiteralType::Closure(closure) => Ok(closure.arity()),
+            LiteralType::Class(class) => match class.find_method("init") {
+                Some(initializer) => initializer.arity(paren),
+                None => Ok(0),
+            },
             _ => Err(RuntimeError::new(
                 paren.to_owned(),
                 "can only call functions and classes".to_owned(),
             )),
         }
     }
+
+    pub fn declaration_span(&self) -> Option<Span> {
+        match self {
+            LiteralType::Function { deceleration, .. } => {
+                deceleration.name.as_ref().map(|name| name.span.to_owned())
+            }
+            LiteralType::Closure(closure) => {
+                closure.proto.name.as_ref().map(|name| name.span.to_owned())
+            }
+            LiteralType::Class(class) => class
+                .find_method("init")
+                .and_then(|initializer| initializer.declaration_span()),
+            _ => None,
+        }
+    }
+
+    pub fn bind(&self, instance: LiteralType) -> LiteralType {
+        match self {
+            LiteralType::Function {
+                deceleration,
+                closure,
+                is_initializer,
+            } => {
+                let environment = Environment::with_enclosing(closure.clone());
+                environment.borrow_mut().define("this".to_owned(), instance);
+
+                LiteralType::Function {
+                    deceleration: deceleration.to_owned(),
+                    closure: environment,
+                    is_initializer: *is_initializer,
+                }
+            }
+            LiteralType::Closure(closure) => LiteralType::Closure(Rc::new(closure.bind(instance))),
+            _ => self.to_owned(),
+        }
+    }
+
+    pub fn check_arity(&self, paren: &Token, count: usize) -> Result<(), RuntimeError> {
+        let arity = self.arity(paren)?;
+
+        if count == arity {
+            return Ok(());
+        }
+
+        let error = RuntimeError::new(
+            paren.to_owned(),
+            format!("expected {} arguments but got {}", arity, count),
+        );
+
+        match self.declaration_span() {
+            Some(span) => Err(error.with_label(&span, "function declared here")),
+            None => Err(error),
+        }
+    }
+}
+
+impl From<f32> for LiteralType {
+    fn from(value: f32) -> LiteralType {
+        LiteralType::Number(value as f64)
+    }
+}
+
+impl From<f64> for LiteralType {
+    fn from(value: f64) -> LiteralType {
+        LiteralType::Number(value)
+    }
+}
+
+impl From<i32> for LiteralType {
+    fn from(value: i32) -> LiteralType {
+        LiteralType::Int(value as i64)
+    }
+}
+
+impl From<i64> for LiteralType {
+    fn from(value: i64) -> LiteralType {
+        LiteralType::Int(value)
+    }
+}
+
+impl From<bool> for LiteralType {
+    fn from(value: bool) -> LiteralType {
+        LiteralType::Bool(value)
+    }
+}
+
+impl From<String> for LiteralType {
+    fn from(value: String) -> LiteralType {
+        LiteralType::String(value)
+    }
+}
+
+impl From<&str> for LiteralType {
+    fn from(value: &str) -> LiteralType {
+        LiteralType::String(value.to_owned())
+    }
+}
+
+impl<T: Into<LiteralType>> From<Option<T>> for LiteralType {
+    fn from(value: Option<T>) -> LiteralType {
+        match value {
+            Some(value) => value.into(),
+            None => LiteralType::Nil,
+        }
+    }
+}
+
+impl<T: Into<LiteralType>> From<Vec<T>> for LiteralType {
+    fn from(values: Vec<T>) -> LiteralType {
+        LiteralType::List(Rc::new(RefCell::new(
+            values.into_iter().map(|value| value.into()).collect(),
+        )))
+    }
+}
+
+#[derive(Debug)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {} but found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl TryFrom<LiteralType> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<f64, ConversionError> {
+        match value {
+            LiteralType::Int(value) => Ok(value as f64),
+            LiteralType::Number(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: "number",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LiteralType> for f32 {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<f32, ConversionError> {
+        f64::try_from(value).map(|value| value as f32)
+    }
+}
+
+impl TryFrom<LiteralType> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<i64, ConversionError> {
+        match value {
+            LiteralType::Int(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: "int",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LiteralType> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<bool, ConversionError> {
+        match value {
+            LiteralType::Bool(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: "bool",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LiteralType> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<String, ConversionError> {
+        match value {
+            LiteralType::String(value) => Ok(value),
+            _ => Err(ConversionError {
+                expected: "string",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LiteralType> for Vec<LiteralType> {
+    type Error = ConversionError;
+
+    fn try_from(value: LiteralType) -> Result<Vec<LiteralType>, ConversionError> {
+        match value {
+            LiteralType::List(items) => Ok(items.borrow().to_owned()),
+            _ => Err(ConversionError {
+                expected: "list",
+                found: value.type_name(),
+            }),
+        }
+    }
 }
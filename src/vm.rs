@@ -0,0 +1,784 @@
+mod chunk;
+mod compiler;
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use fnv::FnvHashMap;
+
+use crate::{
+    error::{Error, RuntimeError, MAX_FRAMES},
+    interpreter::{stdlib, CallFrame},
+    module::{self, Module, Modules},
+    operators,
+    token::Token,
+    types::{Class, Instance, LiteralType, Map, ModuleGlobals, Namespace},
+    Program,
+};
+
+use self::{
+    chunk::{Op, Proto},
+    compiler::Compiler,
+};
+
+pub type Globals = Rc<RefCell<FnvHashMap<String, LiteralType>>>;
+
+pub struct Closure {
+    pub proto: Rc<Proto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // Set for methods bound to an instance, which gets passed in slot 0.
+    pub receiver: Option<LiteralType>,
+    // The globals of the module the closure was created in.
+    pub globals: Globals,
+}
+
+impl Closure {
+    pub fn name(&self) -> Option<&str> {
+        self.proto.name.as_ref().map(|name| name.lexeme.as_str())
+    }
+
+    pub fn arity(&self) -> usize {
+        self.proto.arity
+    }
+
+    pub fn bind(&self, receiver: LiteralType) -> Closure {
+        Closure {
+            proto: self.proto.clone(),
+            upvalues: self.upvalues.clone(),
+            receiver: Some(receiver),
+            globals: self.globals.clone(),
+        }
+    }
+}
+
+pub enum Upvalue {
+    Open(usize),
+    Closed(LiteralType),
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize,
+}
+
+// Where to go when an error is raised inside a try block.
+struct Handler {
+    frames: usize,
+    slot: usize,
+    target: usize,
+    // Handlers that only run a finally block keep the error to raise it
+    // again afterwards, instead of giving it to a catch clause.
+    finally: bool,
+}
+
+impl Frame {
+    fn token(&self) -> &Token {
+        self.closure.proto.chunk.token(self.ip - 1)
+    }
+}
+
+pub struct Vm {
+    stack: Vec<LiteralType>,
+    frames: Vec<Frame>,
+    globals: Globals,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    modules: Modules,
+    handlers: Vec<Handler>,
+    // Errors waiting for a finally block to end, by the stack slot it keeps
+    // them in.
+    pending: Vec<(usize, RuntimeError)>,
+}
+
+impl Default for Vm {
+    fn default() -> Vm {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: global_table(),
+            open_upvalues: Vec::new(),
+            modules: FnvHashMap::default(),
+            handlers: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), Error> {
+        let program = crate::parse(source)?;
+        self.interpret(&program)?;
+
+        Ok(())
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let program = crate::parse_file(path)?;
+        self.interpret(&program)?;
+
+        Ok(())
+    }
+
+    pub fn set_global<T: Into<LiteralType>>(&mut self, name: &str, value: T) {
+        self.globals
+            .borrow_mut()
+            .insert(name.to_owned(), value.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LiteralType> {
+        self.globals.borrow().get(name).cloned()
+    }
+
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Vec<LiteralType>, Token) -> Result<LiteralType, RuntimeError> + 'static,
+    {
+        self.set_global(
+            name,
+            LiteralType::NativeFunction {
+                name: name.to_owned(),
+                arity,
+                func: Rc::new(func),
+            },
+        );
+    }
+
+    pub fn interpret(&mut self, program: &Program) -> Result<(), Error> {
+        let proto = Compiler::new().compile(&program.statements)?;
+        let closure = Rc::new(Closure {
+            proto,
+            upvalues: Vec::new(),
+            receiver: None,
+            globals: self.globals.clone(),
+        });
+
+        self.stack.push(LiteralType::Closure(closure.clone()));
+
+        let result = self.execute(Frame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            self.handlers.clear();
+            self.pending.clear();
+        }
+
+        Ok(result?)
+    }
+
+    fn execute(&mut self, mut frame: Frame) -> Result<(), RuntimeError> {
+        loop {
+            match self.dispatch(&mut frame) {
+                Ok(()) => return Ok(()),
+                Err(error) => self.catch(&mut frame, error)?,
+            }
+        }
+    }
+
+    // Unwinds to the innermost try block, leaving the error where its catch
+    // clause or finally block expects it.
+    fn catch(&mut self, frame: &mut Frame, error: RuntimeError) -> Result<(), RuntimeError> {
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(error),
+        };
+
+        if handler.frames < self.frames.len() {
+            *frame = self.frames.drain(handler.frames..).next().unwrap();
+        }
+
+        self.close_upvalues(handler.slot);
+        self.stack.truncate(handler.slot);
+
+        if handler.finally {
+            // Anything kept at or above this slot belonged to a finally block
+            // that was left early, so it will never be raised again.
+            self.pending.retain(|(slot, _)| *slot < handler.slot);
+            self.pending.push((handler.slot, error));
+            self.stack.push(LiteralType::Nil);
+        } else {
+            self.stack.push(error.to_value());
+        }
+
+        frame.ip = handler.target;
+
+        Ok(())
+    }
+
+    // Runs until the script returns or something raises an error.
+    fn dispatch(&mut self, frame: &mut Frame) -> Result<(), RuntimeError> {
+        loop {
+            let op = frame.closure.proto.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Constant(index) => {
+                    let value = frame.closure.proto.chunk.constants[index as usize].to_owned();
+                    self.stack.push(value);
+                }
+                Op::Nil => self.stack.push(LiteralType::Nil),
+                Op::True => self.stack.push(LiteralType::Bool(true)),
+                Op::False => self.stack.push(LiteralType::Bool(false)),
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::Duplicate(count) => {
+                    let start = self.stack.len() - count as usize;
+                    self.stack.extend_from_within(start..);
+                }
+                Op::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot as usize].to_owned();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    self.stack[frame.base + slot as usize] = self.peek(0).to_owned();
+                }
+                Op::GetGlobal(name) => {
+                    let name = self.constant_name(frame, name);
+
+                    let value = frame.closure.globals.borrow().get(name).cloned();
+
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => {
+                            let message = format!("'{}' is undefined", name);
+                            return Err(self.error(frame, message));
+                        }
+                    }
+                }
+                Op::DefineGlobal(name) => {
+                    let name = self.constant_name(frame, name).to_owned();
+                    let value = self.stack.pop().unwrap();
+
+                    frame.closure.globals.borrow_mut().insert(name, value);
+                }
+                Op::SetGlobal(name) => {
+                    let name = self.constant_name(frame, name);
+                    let value = self.peek(0).to_owned();
+
+                    match frame.closure.globals.borrow_mut().get_mut(name) {
+                        Some(slot) => *slot = value,
+                        None => {
+                            let message = format!("'{}' variable undefined", name);
+                            return Err(self.error(frame, message));
+                        }
+                    }
+                }
+                Op::GetUpvalue(index) => {
+                    let value = match &*frame.closure.upvalues[index as usize].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].to_owned(),
+                        Upvalue::Closed(value) => value.to_owned(),
+                    };
+
+                    self.stack.push(value);
+                }
+                Op::SetUpvalue(index) => {
+                    let value = self.peek(0).to_owned();
+
+                    match &mut *frame.closure.upvalues[index as usize].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                Op::GetProperty => {
+                    let object = self.stack.pop().unwrap();
+
+                    match object {
+                        LiteralType::Instance(instance) => {
+                            let value = Instance::get(&instance, frame.token())
+                                .map_err(|e| self.trace(frame, e))?;
+
+                            self.stack.push(value);
+                        }
+                        LiteralType::Module(namespace) => {
+                            let value = namespace
+                                .get(frame.token())
+                                .map_err(|e| self.trace(frame, e))?;
+
+                            self.stack.push(value);
+                        }
+                        _ => {
+                            return Err(
+                                self.error(frame, "only instances have properties".to_owned())
+                            )
+                        }
+                    }
+                }
+                Op::SetProperty => {
+                    let value = self.stack.pop().unwrap();
+                    let object = self.stack.pop().unwrap();
+
+                    match object {
+                        LiteralType::Instance(instance) => {
+                            instance.borrow_mut().set(frame.token(), value.to_owned());
+                            self.stack.push(value);
+                        }
+                        _ => return Err(self.error(frame, "only instances have fields".to_owned())),
+                    }
+                }
+                Op::GetSuper(name) => {
+                    let superclass = self.stack.pop().unwrap();
+                    let this = self.stack.pop().unwrap();
+                    let name = self.constant_name(frame, name);
+
+                    match superclass {
+                        LiteralType::Class(superclass) => match superclass.find_method(name) {
+                            Some(method) => self.stack.push(method.bind(this)),
+                            None => {
+                                let message = format!("undefined property '{}'", name);
+                                return Err(self.error(frame, message));
+                            }
+                        },
+                        _ => unreachable!(),
+                    }
+                }
+                Op::GetIndex => {
+                    let index = self.stack.pop().unwrap();
+                    let object = self.stack.pop().unwrap();
+                    let value = operators::index(frame.token(), object, index)
+                        .map_err(|e| self.trace(frame, e))?;
+
+                    self.stack.push(value);
+                }
+                Op::SetIndex => {
+                    let value = self.stack.pop().unwrap();
+                    let index = self.stack.pop().unwrap();
+                    let object = self.stack.pop().unwrap();
+                    let value = operators::set_index(frame.token(), object, index, value)
+                        .map_err(|e| self.trace(frame, e))?;
+
+                    self.stack.push(value);
+                }
+                Op::Binary => {
+                    let right = self.stack.pop().unwrap();
+                    let left = self.stack.pop().unwrap();
+                    let value = operators::binary(frame.token(), left, right)
+                        .map_err(|e| self.trace(frame, e))?;
+
+                    self.stack.push(value);
+                }
+                Op::Unary => {
+                    let right = self.stack.pop().unwrap();
+                    let value =
+                        operators::unary(frame.token(), right).map_err(|e| self.trace(frame, e))?;
+
+                    self.stack.push(value);
+                }
+                Op::Jump(target) => frame.ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !operators::is_truthy(self.peek(0)) {
+                        frame.ip = target as usize;
+                    }
+                }
+                Op::Call(count) => self.call(frame, count as usize)?,
+                Op::Closure(index) => {
+                    let proto = frame.closure.proto.chunk.protos[index as usize].clone();
+                    let upvalues = proto
+                        .upvalues
+                        .iter()
+                        .map(|(is_local, index)| {
+                            if *is_local {
+                                self.capture_upvalue(frame.base + *index as usize)
+                            } else {
+                                frame.closure.upvalues[*index as usize].clone()
+                            }
+                        })
+                        .collect();
+
+                    self.stack.push(LiteralType::Closure(Rc::new(Closure {
+                        proto,
+                        upvalues,
+                        receiver: None,
+                        globals: frame.closure.globals.clone(),
+                    })));
+                }
+                Op::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                Op::Return => {
+                    let value = self.stack.pop().unwrap();
+
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            *frame = caller;
+                            self.stack.push(value);
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                Op::Class {
+                    name,
+                    methods,
+                    inherits,
+                } => {
+                    let name = self.constant_name(frame, name).to_owned();
+                    let methods = self.stack.split_off(self.stack.len() - methods as usize);
+
+                    let superclass = if inherits {
+                        match self.peek(0) {
+                            LiteralType::Class(superclass) => Some(superclass.clone()),
+                            _ => {
+                                return Err(
+                                    self.error(frame, "superclass must be a class".to_owned())
+                                )
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
+                    let methods = methods
+                        .into_iter()
+                        .map(|method| match &method {
+                            LiteralType::Closure(closure) => {
+                                (closure.name().unwrap().to_owned(), method)
+                            }
+                            _ => unreachable!(),
+                        })
+                        .collect();
+
+                    self.stack.push(LiteralType::Class(Rc::new(Class {
+                        name,
+                        superclass,
+                        methods,
+                    })));
+                }
+                Op::List(count) => {
+                    let items = self.stack.split_off(self.stack.len() - count as usize);
+
+                    self.stack
+                        .push(LiteralType::List(Rc::new(RefCell::new(items))));
+                }
+                Op::Interpolate(count) => {
+                    let parts = self.stack.split_off(self.stack.len() - count as usize);
+                    let string = parts.iter().map(|part| part.to_string()).collect();
+
+                    self.stack.push(LiteralType::String(string));
+                }
+                Op::Map(count) => {
+                    let values = self.stack.split_off(self.stack.len() - count as usize * 2);
+                    let mut map = Map::default();
+
+                    for entry in values.chunks(2) {
+                        let key = entry[0].to_key(frame.token());
+                        let key = key.map_err(|e| self.trace(frame, e))?;
+
+                        map.insert(key, entry[0].to_owned(), entry[1].to_owned());
+                    }
+
+                    self.stack
+                        .push(LiteralType::Map(Rc::new(RefCell::new(map))));
+                }
+                Op::Range => {
+                    let end = self.stack.pop().unwrap();
+                    let start = self.stack.pop().unwrap();
+
+                    match (start, end) {
+                        (LiteralType::Int(start), LiteralType::Int(end)) => {
+                            self.stack.push(LiteralType::Int(start));
+                            self.stack.push(LiteralType::Int(end.saturating_add(1)));
+                        }
+                        _ => return Err(self.error(frame, "range must be an int".to_owned())),
+                    }
+                }
+                Op::RangeNext { slot, exit } => {
+                    let slot = frame.base + slot as usize;
+
+                    match (&self.stack[slot], &self.stack[slot + 1]) {
+                        (LiteralType::Int(current), LiteralType::Int(end)) if current < end => {
+                            let current = *current;
+
+                            self.stack[slot] = LiteralType::Int(current + 1);
+                            self.stack[slot + 2] = LiteralType::Int(current);
+                        }
+                        _ => frame.ip = exit as usize,
+                    }
+                }
+                Op::Iterate => {
+                    let iterable = self.stack.pop().unwrap();
+                    let items = operators::iterate(frame.token(), iterable)
+                        .map_err(|e| self.trace(frame, e))?;
+
+                    self.stack
+                        .push(LiteralType::List(Rc::new(RefCell::new(items))));
+                    self.stack.push(LiteralType::Int(0));
+                }
+                Op::IterNext { slot, exit } => {
+                    let slot = frame.base + slot as usize;
+
+                    let next = match (&self.stack[slot], &self.stack[slot + 1]) {
+                        (LiteralType::List(items), LiteralType::Int(index)) => {
+                            items.borrow().get(*index as usize).cloned()
+                        }
+                        _ => unreachable!(),
+                    };
+
+                    match next {
+                        Some(item) => {
+                            if let LiteralType::Int(index) = &mut self.stack[slot + 1] {
+                                *index += 1;
+                            }
+
+                            self.stack[slot + 2] = item;
+                        }
+                        None => frame.ip = exit as usize,
+                    }
+                }
+                Op::Try {
+                    target,
+                    slots,
+                    finally,
+                } => self.handlers.push(Handler {
+                    frames: self.frames.len(),
+                    slot: frame.base + slots as usize,
+                    target: target as usize,
+                    finally,
+                }),
+                Op::EndTry => {
+                    self.handlers.pop();
+                }
+                Op::Throw => {
+                    let value = self.stack.pop().unwrap();
+                    let error = RuntimeError::thrown(frame.token().to_owned(), value);
+
+                    return Err(self.trace(frame, error));
+                }
+                Op::Rethrow => {
+                    self.stack.pop();
+                    let slot = self.stack.len();
+
+                    while let Some((pending_slot, error)) = self.pending.pop() {
+                        if pending_slot == slot {
+                            return Err(error);
+                        }
+                    }
+
+                    unreachable!();
+                }
+                Op::Match { pattern, exit } => {
+                    let pattern = &frame.closure.proto.chunk.patterns[pattern as usize];
+                    let mut bindings = Vec::new();
+
+                    if operators::match_pattern(pattern, self.peek(0), &mut bindings) {
+                        self.stack.extend(bindings);
+                    } else {
+                        frame.ip = exit as usize;
+                    }
+                }
+                Op::NoMatch => {
+                    let message = format!("no match arm for {}", self.peek(0).to_repr());
+
+                    return Err(self.error(frame, message));
+                }
+                Op::Import(index) => {
+                    let (module, proto) = &frame.closure.proto.chunk.imports[index as usize];
+                    let namespace = self.import(module, proto.clone())?;
+
+                    self.stack.push(LiteralType::Module(namespace));
+                }
+            }
+        }
+    }
+
+    fn import(
+        &mut self,
+        module: &Rc<Module>,
+        proto: Rc<Proto>,
+    ) -> Result<Rc<Namespace>, RuntimeError> {
+        module::import(
+            self,
+            |vm| &mut vm.modules,
+            module,
+            |vm| {
+                let globals = global_table();
+                let closure = Rc::new(Closure {
+                    proto,
+                    upvalues: Vec::new(),
+                    receiver: None,
+                    globals: globals.clone(),
+                });
+
+                let base = vm.stack.len();
+                vm.stack.push(LiteralType::Closure(closure.clone()));
+
+                // The module's script returns to here rather than to the importer.
+                let callers = std::mem::take(&mut vm.frames);
+                let handlers = std::mem::take(&mut vm.handlers);
+                let result = vm.execute(Frame {
+                    closure,
+                    ip: 0,
+                    base,
+                });
+                vm.frames = callers;
+                vm.handlers = handlers;
+                result?;
+
+                Ok(ModuleGlobals::Table(globals))
+            },
+        )
+    }
+
+    fn call(&mut self, frame: &mut Frame, count: usize) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - count - 1;
+        let callee = self.stack[base].to_owned();
+        callee
+            .check_arity(frame.token(), count)
+            .map_err(|e| self.trace(frame, e))?;
+
+        match callee {
+            LiteralType::Closure(closure) => self.call_closure(frame, closure, base),
+            LiteralType::NativeFunction { name, func, .. } => {
+                let paren = frame.token().to_owned();
+                let args = self.stack.split_off(base + 1);
+                self.stack.pop();
+
+                let value = func(args, paren.to_owned()).map_err(|e| {
+                    let native = CallFrame {
+                        name,
+                        native: true,
+                        call_site: paren.to_owned(),
+                    };
+
+                    e.with_backtrace(&[self.backtrace(frame), vec![native]].concat())
+                })?;
+
+                self.stack.push(value);
+
+                Ok(())
+            }
+            LiteralType::Class(class) => {
+                let instance = LiteralType::Instance(Rc::new(RefCell::new(Instance {
+                    class: class.clone(),
+                    fields: FnvHashMap::default(),
+                })));
+
+                self.stack[base] = instance.to_owned();
+
+                match class.find_method("init") {
+                    Some(LiteralType::Closure(initializer)) => {
+                        let initializer = Rc::new(initializer.bind(instance));
+                        self.call_closure(frame, initializer, base)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn call_closure(
+        &mut self,
+        frame: &mut Frame,
+        closure: Rc<Closure>,
+        base: usize,
+    ) -> Result<(), RuntimeError> {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(frame, "stack overflow".to_owned()));
+        }
+
+        if let Some(receiver) = &closure.receiver {
+            self.stack[base] = receiver.to_owned();
+        }
+
+        let caller = std::mem::replace(
+            frame,
+            Frame {
+                closure,
+                ip: 0,
+                base,
+            },
+        );
+        self.frames.push(caller);
+
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+
+        upvalue
+    }
+
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].to_owned());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn peek(&self, distance: usize) -> &LiteralType {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn constant_name<'a>(&self, frame: &'a Frame, index: u16) -> &'a str {
+        match &frame.closure.proto.chunk.constants[index as usize] {
+            LiteralType::String(name) => name,
+            _ => unreachable!(),
+        }
+    }
+
+    fn error(&self, frame: &Frame, message: String) -> RuntimeError {
+        self.trace(frame, RuntimeError::new(frame.token().to_owned(), message))
+    }
+
+    fn trace(&self, frame: &Frame, error: RuntimeError) -> RuntimeError {
+        let backtrace = self.backtrace(frame);
+
+        if backtrace.is_empty() {
+            error
+        } else {
+            error.with_backtrace(&backtrace)
+        }
+    }
+
+    // The same frames the tree walker reports: every call below the script,
+    // named after the function and pointing at the call that entered it.
+    fn backtrace(&self, frame: &Frame) -> Vec<CallFrame> {
+        let mut callers: Vec<&Frame> = self.frames.iter().collect();
+        callers.push(frame);
+
+        callers
+            .windows(2)
+            .map(|pair| CallFrame {
+                name: pair[1].closure.name().unwrap_or("anonymous").to_owned(),
+                native: false,
+                call_site: pair[0].token().to_owned(),
+            })
+            .collect()
+    }
+}
+
+fn global_table() -> Globals {
+    Rc::new(RefCell::new(stdlib::functions().into_iter().collect()))
+}
@@ -0,0 +1,133 @@
+use std::rc::Rc;
+
+use crate::{ast::Pattern, module::Module, token::Token, types::LiteralType};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Constant(u16),
+    Nil,
+    True,
+    False,
+    Pop,
+    // Pushes copies of the top n values, in the same order.
+    Duplicate(u8),
+    GetLocal(u16),
+    SetLocal(u16),
+    GetGlobal(u16),
+    DefineGlobal(u16),
+    SetGlobal(u16),
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    GetProperty,
+    SetProperty,
+    GetSuper(u16),
+    GetIndex,
+    SetIndex,
+    Binary,
+    Unary,
+    Jump(u32),
+    JumpIfFalse(u32),
+    Call(u8),
+    Closure(u16),
+    CloseUpvalue,
+    Return,
+    Class {
+        name: u16,
+        methods: u16,
+        inherits: bool,
+    },
+    List(u16),
+    Interpolate(u16),
+    Map(u16),
+    Range,
+    RangeNext {
+        slot: u16,
+        exit: u32,
+    },
+    Iterate,
+    IterNext {
+        slot: u16,
+        exit: u32,
+    },
+    Import(u16),
+    Try {
+        target: u32,
+        slots: u16,
+        finally: bool,
+    },
+    EndTry,
+    Throw,
+    Rethrow,
+    Match {
+        pattern: u16,
+        exit: u32,
+    },
+    NoMatch,
+}
+
+pub struct Proto {
+    pub name: Option<Token>,
+    pub arity: usize,
+    pub is_initializer: bool,
+    pub chunk: Chunk,
+    // For every upvalue the closure captures: whether it is a local of the
+    // enclosing function, and its index there.
+    pub upvalues: Vec<(bool, u16)>,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<LiteralType>,
+    pub protos: Vec<Rc<Proto>>,
+    // Every module imported here, with the script it compiled to.
+    pub imports: Vec<(Rc<Module>, Rc<Proto>)>,
+    pub patterns: Vec<Rc<Pattern>>,
+    tokens: Vec<Token>,
+    token_indices: Vec<u32>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, op: Op, token: Option<&Token>) -> usize {
+        let index = match token {
+            Some(token) => {
+                let is_last = self.tokens.last().is_some_and(|last| {
+                    last.span.start == token.span.start
+                        && Rc::ptr_eq(&last.span.source, &token.span.source)
+                });
+
+                if !is_last {
+                    self.tokens.push(token.to_owned());
+                }
+
+                (self.tokens.len() - 1) as u32
+            }
+            None => u32::MAX,
+        };
+
+        self.code.push(op);
+        self.token_indices.push(index);
+
+        self.code.len() - 1
+    }
+
+    // Every instruction that can fail is written with the token it came from.
+    pub fn token(&self, offset: usize) -> &Token {
+        &self.tokens[self.token_indices[offset] as usize]
+    }
+
+    pub fn add_constant(&mut self, value: LiteralType) -> usize {
+        if let LiteralType::String(string) = &value {
+            let existing = self.constants.iter().position(
+                |constant| matches!(constant, LiteralType::String(other) if other == string),
+            );
+
+            if let Some(index) = existing {
+                return index;
+            }
+        }
+
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
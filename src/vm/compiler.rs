@@ -0,0 +1,1105 @@
+use std::{convert::TryFrom, iter, rc::Rc};
+
+use crate::{
+    ast::{Expr, Function, Pattern, Stmt},
+    error::ParseError,
+    token::{Token, TokenType},
+    types::LiteralType,
+};
+
+use super::chunk::{Chunk, Op, Proto};
+
+#[derive(PartialEq, Clone, Copy)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    // Where the local lives in the frame. Locals bound by a match can sit
+    // above operands that are still on the stack, so this isn't always its
+    // position in `locals`.
+    slot: u16,
+    depth: Option<usize>,
+    captured: bool,
+}
+
+struct Loop {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// A try block or catch clause being compiled. Leaving one early with
+// `break`, `continue` or `return` has to remove its handler and run its
+// finally block on the way out.
+struct TryBlock {
+    loop_count: usize,
+    has_handler: bool,
+    finally: Option<Rc<Stmt>>,
+}
+
+struct FunctionState {
+    name: Option<Token>,
+    arity: usize,
+    kind: FunctionKind,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<(bool, u16)>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    tries: Vec<TryBlock>,
+    // Values pushed by the expression being compiled that are waiting for
+    // the rest of it.
+    temporaries: usize,
+}
+
+impl FunctionState {
+    fn new(name: Option<Token>, arity: usize, kind: FunctionKind) -> FunctionState {
+        // Slot 0 holds the function being called, or the receiver for methods.
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+
+        FunctionState {
+            name,
+            arity,
+            kind,
+            chunk: Chunk::default(),
+            locals: vec![Local {
+                name: slot_zero.to_owned(),
+                slot: 0,
+                depth: Some(0),
+                captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+            tries: Vec::new(),
+            temporaries: 0,
+        }
+    }
+}
+
+pub struct Compiler {
+    states: Vec<FunctionState>,
+    token: Option<Token>,
+    errors: Vec<ParseError>,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        Compiler {
+            states: vec![FunctionState::new(None, 0, FunctionKind::Script)],
+            token: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Stmt]) -> Result<Rc<Proto>, Vec<ParseError>> {
+        for statement in statements {
+            self.statement(statement);
+        }
+
+        let proto = self.end_function();
+
+        if self.errors.is_empty() {
+            Ok(proto)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr);
+                self.emit(Op::Pop);
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+
+                for statement in statements {
+                    self.statement(statement);
+                }
+
+                self.end_scope();
+            }
+            Stmt::VarDecl { name, initializer } => {
+                self.set_token(name);
+
+                if self.state().scope_depth > 0 {
+                    self.add_local(name.lexeme.to_owned());
+                }
+
+                match initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => {
+                        self.emit(Op::Nil);
+                    }
+                }
+
+                self.define_variable(name);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                let condition = match condition {
+                    Some(condition) => condition,
+                    None => return,
+                };
+
+                let loop_start = self.state().chunk.code.len();
+
+                self.expression(condition);
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Pop);
+
+                self.begin_loop();
+                self.statement(body);
+                self.patch_continues();
+
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                    self.emit(Op::Pop);
+                }
+
+                self.emit(Op::Jump(loop_start as u32));
+
+                self.patch_jump(exit);
+                self.emit(Op::Pop);
+                self.end_loop();
+            }
+            Stmt::ForRange {
+                name,
+                range_start,
+                range_end,
+                body,
+            } => {
+                self.begin_scope();
+
+                self.operands([range_start.as_ref(), range_end.as_ref()]);
+                self.set_token(name);
+                self.emit(Op::Range);
+
+                self.for_loop(name, |slot| Op::RangeNext { slot, exit: 0 }, body);
+
+                self.end_scope();
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.begin_scope();
+
+                self.expression(iterable);
+                self.set_token(name);
+                self.emit(Op::Iterate);
+
+                self.for_loop(name, |slot| Op::IterNext { slot, exit: 0 }, body);
+
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+
+                let else_jump = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Pop);
+                self.statement(then_branch);
+
+                let end_jump = self.emit(Op::Jump(0));
+
+                self.patch_jump(else_jump);
+                self.emit(Op::Pop);
+
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+
+                self.patch_jump(end_jump);
+            }
+            Stmt::FunctionDecl(function) => {
+                let name = function.name.as_ref().unwrap();
+                self.set_token(name);
+
+                if self.state().scope_depth > 0 {
+                    self.add_local(name.lexeme.to_owned());
+                    self.mark_initialized();
+                }
+
+                self.function(function, FunctionKind::Function);
+                self.define_variable(name);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.set_token(name);
+
+                let class_slot = if self.state().scope_depth > 0 {
+                    self.emit(Op::Nil);
+                    self.add_local(name.lexeme.to_owned());
+                    self.mark_initialized();
+
+                    Some(self.state().locals.len() - 1)
+                } else {
+                    None
+                };
+
+                if let Some(superclass) = superclass {
+                    self.begin_scope();
+                    self.expression(superclass);
+                    self.add_local("super".to_owned());
+                    self.mark_initialized();
+                }
+
+                for method in methods {
+                    let kind = match &method.name {
+                        Some(name) if name.lexeme == "init" => FunctionKind::Initializer,
+                        _ => FunctionKind::Method,
+                    };
+
+                    self.function(method, kind);
+                }
+
+                self.set_token(name);
+                let class_name = self.identifier_constant(name);
+                let method_count = self.checked_u16(methods.len(), "too many methods in one class");
+                self.emit(Op::Class {
+                    name: class_name,
+                    methods: method_count,
+                    inherits: superclass.is_some(),
+                });
+
+                match class_slot {
+                    Some(slot) => {
+                        self.emit(Op::SetLocal(slot as u16));
+                        self.emit(Op::Pop);
+                    }
+                    None => {
+                        self.emit(Op::DefineGlobal(class_name));
+                    }
+                }
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+            }
+            Stmt::Return { keyword, value } => {
+                self.expression(value);
+                self.set_token(keyword);
+
+                if self.state().kind == FunctionKind::Initializer {
+                    self.emit(Op::Pop);
+                    self.emit(Op::GetLocal(0));
+                }
+
+                // Finally blocks run with the return value kept in a hidden
+                // local, so their own locals get the right slots.
+                if !self.state().tries.is_empty() {
+                    self.add_local(" return".to_owned());
+                    self.mark_initialized();
+                    self.exit_tries(0);
+                    self.state_mut().locals.pop();
+                }
+
+                self.set_token(keyword);
+                self.emit(Op::Return);
+            }
+            Stmt::Break(keyword) | Stmt::Continue(keyword) => {
+                self.set_token(keyword);
+
+                let loop_count = self.state().loops.len();
+                let first_try = self
+                    .state()
+                    .tries
+                    .iter()
+                    .position(|try_block| try_block.loop_count >= loop_count);
+
+                if let Some(first_try) = first_try {
+                    self.exit_tries(first_try);
+                    self.set_token(keyword);
+                }
+
+                let loop_depth = self.state().loops.last().unwrap().scope_depth;
+                self.discard_locals(loop_depth);
+
+                let jump = self.emit(Op::Jump(0));
+                let current_loop = self.state_mut().loops.last_mut().unwrap();
+
+                if matches!(stmt, Stmt::Break(_)) {
+                    current_loop.breaks.push(jump);
+                } else {
+                    current_loop.continues.push(jump);
+                }
+            }
+            Stmt::Import {
+                path,
+                name,
+                names,
+                module,
+                ..
+            } => {
+                let module = module.get().unwrap();
+
+                let proto = match Compiler::new().compile(&module.statements) {
+                    Ok(proto) => proto,
+                    Err(errors) => {
+                        self.errors.extend(errors);
+                        return;
+                    }
+                };
+
+                let imports = &mut self.state_mut().chunk.imports;
+                imports.push((module.clone(), proto));
+                let index = imports.len() - 1;
+
+                let index = self.checked_u16(index, "too many imports in one chunk");
+
+                match name {
+                    Some(name) => {
+                        self.set_token(path);
+                        self.emit(Op::Import(index));
+                        self.define_variable(name);
+                    }
+                    // Every name gets the module again, which only runs once.
+                    None => {
+                        for name in names {
+                            self.set_token(path);
+                            self.emit(Op::Import(index));
+                            self.set_token(name);
+                            self.emit(Op::GetProperty);
+                            self.define_variable(name);
+                        }
+                    }
+                }
+            }
+            Stmt::Export { declaration, .. } => self.statement(declaration),
+            Stmt::Throw { keyword, value } => {
+                self.expression(value);
+                self.set_token(keyword);
+                self.emit(Op::Throw);
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                // Handlers put the error right where the try block's locals
+                // started, so it becomes the first local of what handles it.
+                let slots = self.state().locals.len() as u16;
+
+                let handler = self.emit(Op::Try {
+                    target: 0,
+                    slots,
+                    finally: catch.is_none(),
+                });
+
+                self.begin_try(true, finally);
+                self.statement(body);
+                self.end_try();
+                self.emit(Op::EndTry);
+
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+
+                let mut exits = vec![self.emit(Op::Jump(0))];
+                self.patch_jump(handler);
+
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.set_token(name);
+                    self.add_local(name.lexeme.to_owned());
+                    self.mark_initialized();
+
+                    // Errors from the catch clause still run the finally block.
+                    let rethrow = finally.as_ref().map(|_| {
+                        self.emit(Op::Try {
+                            target: 0,
+                            slots,
+                            finally: true,
+                        })
+                    });
+
+                    self.begin_try(rethrow.is_some(), finally);
+                    self.statement(handler);
+                    self.end_try();
+
+                    if rethrow.is_some() {
+                        self.emit(Op::EndTry);
+                    }
+
+                    self.end_scope();
+
+                    if let Some(finally) = finally {
+                        self.statement(finally);
+                    }
+
+                    if let Some(rethrow) = rethrow {
+                        exits.push(self.emit(Op::Jump(0)));
+                        self.patch_jump(rethrow);
+                    }
+                }
+
+                if let Some(finally) = finally {
+                    self.begin_scope();
+                    self.add_local(" error".to_owned());
+                    self.mark_initialized();
+
+                    self.statement(finally);
+                    self.emit(Op::Rethrow);
+
+                    // Nothing runs after the rethrow, so the error local is
+                    // forgotten without popping it.
+                    self.state_mut().locals.pop();
+                    self.state_mut().scope_depth -= 1;
+                }
+
+                for exit in exits {
+                    self.patch_jump(exit);
+                }
+            }
+        }
+    }
+
+    fn begin_try(&mut self, has_handler: bool, finally: &Option<Rc<Stmt>>) {
+        let loop_count = self.state().loops.len();
+
+        self.state_mut().tries.push(TryBlock {
+            loop_count,
+            has_handler,
+            finally: finally.to_owned(),
+        });
+    }
+
+    fn end_try(&mut self) {
+        self.state_mut().tries.pop();
+    }
+
+    // Removes the handlers of every try block from `first` inwards and runs
+    // their finally blocks, innermost first, before jumping out of them. Each
+    // finally block is compiled as if it were outside its own try block.
+    fn exit_tries(&mut self, first: usize) {
+        let mut exited = Vec::new();
+
+        while self.state().tries.len() > first {
+            let try_block = self.state_mut().tries.pop().unwrap();
+
+            if try_block.has_handler {
+                self.emit(Op::EndTry);
+            }
+
+            if let Some(finally) = &try_block.finally {
+                self.statement(finally);
+            }
+
+            exited.push(try_block);
+        }
+
+        exited.reverse();
+        self.state_mut().tries.extend(exited);
+    }
+
+    // Shared by both kinds of for loop. The range or iterator state is already
+    // on the stack as two values, and gets two hidden locals before the loop variable.
+    fn for_loop(&mut self, name: &Token, next: fn(u16) -> Op, body: &Stmt) {
+        self.add_local(" state".to_owned());
+        self.mark_initialized();
+        self.add_local(" index".to_owned());
+        self.mark_initialized();
+
+        self.emit(Op::Nil);
+        self.add_local(name.lexeme.to_owned());
+        self.mark_initialized();
+
+        let slot = (self.state().locals.len() - 3) as u16;
+        let loop_start = self.emit(next(slot));
+
+        self.begin_loop();
+        self.statement(body);
+        self.patch_continues();
+
+        self.emit(Op::Jump(loop_start as u32));
+        self.patch_jump(loop_start);
+        self.end_loop();
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Group(expr) => self.expression(expr),
+            Expr::Literal(value) => match value {
+                LiteralType::Nil => {
+                    self.emit(Op::Nil);
+                }
+                LiteralType::Bool(true) => {
+                    self.emit(Op::True);
+                }
+                LiteralType::Bool(false) => {
+                    self.emit(Op::False);
+                }
+                _ => {
+                    let constant = self.make_constant(value.to_owned());
+                    self.emit(Op::Constant(constant));
+                }
+            },
+            Expr::Variable { name, .. } => {
+                self.set_token(name);
+                self.named_variable(&name.lexeme, false);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.operands([left.as_ref(), right.as_ref()]);
+                self.set_token(operator);
+                self.emit(Op::Binary);
+            }
+            Expr::Unary { operator, right } => {
+                self.expression(right);
+                self.set_token(operator);
+                self.emit(Op::Unary);
+            }
+            Expr::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                self.operands(iter::once(callee.as_ref()).chain(args));
+                self.set_token(paren);
+
+                let count = self.checked_u16(args.len(), "too many arguments");
+                match u8::try_from(count) {
+                    Ok(count) => {
+                        self.emit(Op::Call(count));
+                    }
+                    Err(_) => self.error("can't have more than 255 arguments"),
+                }
+            }
+            Expr::Assign { name, value, .. } => {
+                self.expression(value);
+                self.set_token(name);
+                self.named_variable(&name.lexeme, true);
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left);
+                self.set_token(operator);
+
+                if operator.t_type == TokenType::Or {
+                    let else_jump = self.emit(Op::JumpIfFalse(0));
+                    let end_jump = self.emit(Op::Jump(0));
+
+                    self.patch_jump(else_jump);
+                    self.emit(Op::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit(Op::JumpIfFalse(0));
+
+                    self.emit(Op::Pop);
+                    self.expression(right);
+                    self.patch_jump(end_jump);
+                }
+            }
+            Expr::Get { object, name } => {
+                self.expression(object);
+                self.set_token(name);
+                self.emit(Op::GetProperty);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                operator,
+            } => {
+                match operator {
+                    Some(operator) => {
+                        let temporaries = self.state().temporaries;
+
+                        // Keep the object for the store while reading the
+                        // current value off a copy.
+                        self.expression(object);
+                        self.emit(Op::Duplicate(1));
+                        self.set_token(name);
+                        self.emit(Op::GetProperty);
+
+                        self.state_mut().temporaries += 2;
+                        self.expression(value);
+                        self.state_mut().temporaries = temporaries;
+
+                        self.set_token(operator);
+                        self.emit(Op::Binary);
+                    }
+                    None => self.operands([object.as_ref(), value.as_ref()]),
+                }
+
+                self.set_token(name);
+                self.emit(Op::SetProperty);
+            }
+            Expr::List(items) => {
+                self.operands(items);
+
+                let count = self.checked_u16(items.len(), "too many items in a list literal");
+                self.emit(Op::List(count));
+            }
+            Expr::Interpolation(parts) => {
+                self.operands(parts);
+
+                let count =
+                    self.checked_u16(parts.len(), "too many parts in an interpolated string");
+                self.emit(Op::Interpolate(count));
+            }
+            Expr::Map { brace, entries } => {
+                self.operands(entries.iter().flat_map(|(key, value)| [key, value]));
+
+                self.set_token(brace);
+
+                let count = self.checked_u16(entries.len(), "too many entries in a map literal");
+                self.emit(Op::Map(count));
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.operands([object.as_ref(), index.as_ref()]);
+                self.set_token(bracket);
+                self.emit(Op::GetIndex);
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+                operator,
+            } => {
+                match operator {
+                    Some(operator) => {
+                        let temporaries = self.state().temporaries;
+
+                        self.operands([object.as_ref(), index.as_ref()]);
+                        self.emit(Op::Duplicate(2));
+                        self.set_token(bracket);
+                        self.emit(Op::GetIndex);
+
+                        self.state_mut().temporaries += 3;
+                        self.expression(value);
+                        self.state_mut().temporaries = temporaries;
+
+                        self.set_token(operator);
+                        self.emit(Op::Binary);
+                    }
+                    None => self.operands([object.as_ref(), index.as_ref(), value.as_ref()]),
+                }
+
+                self.set_token(bracket);
+                self.emit(Op::SetIndex);
+            }
+            Expr::Lambda(function) => self.function(function, FunctionKind::Function),
+            Expr::This { keyword, .. } => {
+                self.set_token(keyword);
+                self.named_variable("this", false);
+            }
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.set_token(keyword);
+                self.named_variable("this", false);
+                self.named_variable("super", false);
+                self.set_token(method);
+
+                let name = self.identifier_constant(method);
+                self.emit(Op::GetSuper(name));
+            }
+            Expr::Match {
+                keyword,
+                value,
+                arms,
+            } => {
+                // The value being matched is kept in a hidden local, which
+                // every arm that matches overwrites with its result.
+                self.expression(value);
+                self.add_local(" match".to_owned());
+                self.mark_initialized();
+
+                let slot = self.state().locals.last().unwrap().slot;
+                let mut exits = Vec::new();
+
+                for arm in arms {
+                    self.begin_scope();
+
+                    let pattern = self.add_pattern(&arm.pattern);
+                    let next_arm = self.emit(Op::Match { pattern, exit: 0 });
+
+                    for name in arm.pattern.bindings() {
+                        self.set_token(name);
+                        self.add_local(name.lexeme.to_owned());
+                        self.mark_initialized();
+                    }
+
+                    let guard_failed = arm.guard.as_ref().map(|guard| {
+                        self.expression(guard);
+                        let jump = self.emit(Op::JumpIfFalse(0));
+                        self.emit(Op::Pop);
+
+                        jump
+                    });
+
+                    self.expression(&arm.body);
+                    self.emit(Op::SetLocal(slot));
+                    self.emit(Op::Pop);
+
+                    let start = self.state().chunk.code.len();
+                    self.end_scope();
+                    let pops = self.state().chunk.code[start..].to_vec();
+
+                    exits.push(self.emit(Op::Jump(0)));
+
+                    // A failed guard leaves the same bindings to pop.
+                    if let Some(guard_failed) = guard_failed {
+                        self.patch_jump(guard_failed);
+                        self.emit(Op::Pop);
+
+                        for op in pops {
+                            self.emit(op);
+                        }
+                    }
+
+                    self.patch_jump(next_arm);
+                }
+
+                self.set_token(keyword);
+                self.emit(Op::NoMatch);
+
+                for exit in exits {
+                    self.patch_jump(exit);
+                }
+
+                // The hidden local now holds the result, which is left on
+                // the stack as the value of the whole match.
+                self.state_mut().locals.pop();
+            }
+        }
+    }
+
+    // Compiles expressions whose values all stay on the stack until the
+    // instruction using them runs.
+    fn operands<'a>(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) {
+        let temporaries = self.state().temporaries;
+
+        for expr in exprs {
+            self.expression(expr);
+            self.state_mut().temporaries += 1;
+        }
+
+        self.state_mut().temporaries = temporaries;
+    }
+
+    fn add_pattern(&mut self, pattern: &Rc<Pattern>) -> u16 {
+        let patterns = &mut self.state_mut().chunk.patterns;
+        patterns.push(pattern.clone());
+        let index = patterns.len() - 1;
+
+        self.checked_u16(index, "too many match arms in one chunk")
+    }
+
+    fn function(&mut self, function: &Rc<Function>, kind: FunctionKind) {
+        self.states.push(FunctionState::new(
+            function.name.to_owned(),
+            function.params.len(),
+            kind,
+        ));
+        self.begin_scope();
+
+        for param in &function.params {
+            self.set_token(param);
+            self.add_local(param.lexeme.to_owned());
+            self.mark_initialized();
+        }
+
+        for statement in &function.body {
+            self.statement(statement);
+        }
+
+        let proto = self.end_function();
+
+        let chunk = &mut self.state_mut().chunk;
+        chunk.protos.push(proto);
+        let index = chunk.protos.len() - 1;
+
+        let index = self.checked_u16(index, "too many functions in one chunk");
+        self.emit(Op::Closure(index));
+    }
+
+    fn end_function(&mut self) -> Rc<Proto> {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit(Op::GetLocal(0));
+        } else {
+            self.emit(Op::Nil);
+        }
+
+        self.emit(Op::Return);
+
+        let state = self.states.pop().unwrap();
+
+        Rc::new(Proto {
+            name: state.name,
+            arity: state.arity,
+            is_initializer: state.kind == FunctionKind::Initializer,
+            chunk: state.chunk,
+            upvalues: state.upvalues,
+        })
+    }
+
+    fn named_variable(&mut self, name: &str, assign: bool) {
+        let depth = self.states.len() - 1;
+
+        let (get, set) = if let Some(index) = self.resolve_local(depth, name) {
+            let slot = self.state().locals[index].slot;
+
+            (Op::GetLocal(slot), Op::SetLocal(slot))
+        } else if let Some(index) = self.resolve_upvalue(depth, name) {
+            (Op::GetUpvalue(index), Op::SetUpvalue(index))
+        } else {
+            let constant = self.make_constant(LiteralType::String(name.to_owned()));
+            (Op::GetGlobal(constant), Op::SetGlobal(constant))
+        };
+
+        self.emit(if assign { set } else { get });
+    }
+
+    fn resolve_local(&mut self, depth: usize, name: &str) -> Option<usize> {
+        self.states[depth]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+    }
+
+    fn resolve_upvalue(&mut self, depth: usize, name: &str) -> Option<u16> {
+        if depth == 0 {
+            return None;
+        }
+
+        if let Some(index) = self.resolve_local(depth - 1, name) {
+            let local = &mut self.states[depth - 1].locals[index];
+            local.captured = true;
+            let slot = local.slot;
+
+            return Some(self.add_upvalue(depth, true, slot));
+        }
+
+        let index = self.resolve_upvalue(depth - 1, name)?;
+
+        Some(self.add_upvalue(depth, false, index))
+    }
+
+    fn add_upvalue(&mut self, depth: usize, is_local: bool, index: u16) -> u16 {
+        let upvalues = &mut self.states[depth].upvalues;
+
+        if let Some(existing) = upvalues
+            .iter()
+            .position(|upvalue| *upvalue == (is_local, index))
+        {
+            return existing as u16;
+        }
+
+        upvalues.push((is_local, index));
+        let count = upvalues.len() - 1;
+
+        self.checked_u16(count, "too many closure variables in function")
+    }
+
+    fn add_local(&mut self, name: String) {
+        // A variable whose initializer is being compiled has no value on the
+        // stack yet, so it doesn't take up a slot.
+        let state = self.state();
+        let count = state
+            .locals
+            .iter()
+            .filter(|local| local.depth.is_some())
+            .count()
+            + state.temporaries;
+        let slot = self.checked_u16(count, "too many local variables in function");
+
+        self.state_mut().locals.push(Local {
+            name,
+            slot,
+            depth: None,
+            captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.state_mut();
+
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(state.scope_depth);
+        }
+    }
+
+    fn define_variable(&mut self, name: &Token) {
+        if self.state().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+
+        let constant = self.identifier_constant(name);
+        self.emit(Op::DefineGlobal(constant));
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state_mut().scope_depth -= 1;
+
+        let depth = self.state().scope_depth;
+        self.discard_locals(depth);
+
+        let state = self.state_mut();
+        while matches!(state.locals.last(), Some(Local { depth: Some(local_depth), .. }) if *local_depth > depth)
+        {
+            state.locals.pop();
+        }
+    }
+
+    // Emits the pops for every local deeper than `depth` without forgetting
+    // about them, so `break` and `continue` can leave nested scopes early.
+    fn discard_locals(&mut self, depth: usize) {
+        let ops: Vec<Op> = self
+            .state()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|local_depth| local_depth > depth))
+            .map(|local| {
+                if local.captured {
+                    Op::CloseUpvalue
+                } else {
+                    Op::Pop
+                }
+            })
+            .collect();
+
+        for op in ops {
+            self.emit(op);
+        }
+    }
+
+    fn begin_loop(&mut self) {
+        let scope_depth = self.state().scope_depth;
+
+        self.state_mut().loops.push(Loop {
+            scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+    }
+
+    fn patch_continues(&mut self) {
+        let continues = std::mem::take(&mut self.state_mut().loops.last_mut().unwrap().continues);
+
+        for jump in continues {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn end_loop(&mut self) {
+        let current_loop = self.state_mut().loops.pop().unwrap();
+
+        for jump in current_loop.breaks {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let chunk = &mut self.state_mut().chunk;
+        let target = chunk.code.len() as u32;
+
+        chunk.code[offset] = match chunk.code[offset] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::RangeNext { slot, .. } => Op::RangeNext { slot, exit: target },
+            Op::IterNext { slot, .. } => Op::IterNext { slot, exit: target },
+            Op::Try { slots, finally, .. } => Op::Try {
+                target,
+                slots,
+                finally,
+            },
+            Op::Match { pattern, .. } => Op::Match {
+                pattern,
+                exit: target,
+            },
+            op => unreachable!("{:?} is not a jump", op),
+        };
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> u16 {
+        self.make_constant(LiteralType::String(name.lexeme.to_owned()))
+    }
+
+    fn make_constant(&mut self, value: LiteralType) -> u16 {
+        let index = self.state_mut().chunk.add_constant(value);
+
+        self.checked_u16(index, "too many constants in one chunk")
+    }
+
+    fn checked_u16(&mut self, value: usize, message: &str) -> u16 {
+        match u16::try_from(value) {
+            Ok(value) => value,
+            Err(_) => {
+                self.error(message);
+                0
+            }
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let token = self.token.to_owned();
+
+        self.state_mut().chunk.write(op, token.as_ref())
+    }
+
+    fn set_token(&mut self, token: &Token) {
+        self.token = Some(token.to_owned());
+    }
+
+    fn error(&mut self, message: &str) {
+        if let Some(token) = &self.token {
+            self.errors
+                .push(ParseError::new(token.to_owned(), message.to_owned()));
+        }
+    }
+
+    fn state(&self) -> &FunctionState {
+        self.states.last().unwrap()
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+}
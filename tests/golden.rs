@@ -0,0 +1,180 @@
+//! Golden-file conformance suite.
+//!
+//! Every `.wind` program under `tests/golden` is run with both backends, and
+//! what it prints is compared with the files next to it:
+//!
+//! - `name.stdout`: everything written to stdout.
+//! - `name.stderr`: everything written to stderr, when there is anything.
+//! - `name.stdin`: fed to the program, when it reads input.
+//!
+//! A program that should fail says so with an `// exit: N` comment; otherwise
+//! it has to exit with 0. Files in a `modules` directory are only there to be
+//! imported by the programs next to it, and aren't run on their own.
+//!
+//! Run with `WIND_BLESS=1` to rewrite `.stdout` and `.stderr` from the tree
+//! walker's output after an intended change.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const BACKENDS: [&str; 2] = ["tree", "vm"];
+
+#[derive(PartialEq, Debug)]
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let bless = env::var_os("WIND_BLESS").is_some();
+
+    let mut programs = Vec::new();
+    collect_programs(&root.join("tests/golden"), &mut programs);
+    programs.sort();
+
+    assert!(!programs.is_empty(), "no programs found in tests/golden");
+
+    let mut failures = Vec::new();
+
+    for program in &programs {
+        let relative = program.strip_prefix(root).unwrap();
+
+        if bless {
+            bless_program(root, relative);
+        }
+
+        let expected = expected_outcome(program);
+
+        for backend in BACKENDS {
+            let actual = run(root, relative, backend);
+
+            if actual != expected {
+                failures.push(describe_failure(relative, backend, &expected, &actual));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} golden runs failed\n\n{}",
+            failures.len(),
+            programs.len() * BACKENDS.len(),
+            failures.join("\n\n")
+        );
+    }
+}
+
+fn collect_programs(dir: &Path, programs: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            if !path.ends_with("modules") {
+                collect_programs(&path, programs);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "wind")
+        {
+            programs.push(path);
+        }
+    }
+}
+
+// The program is passed relative to the crate root so the paths in
+// diagnostics don't depend on where the repository is checked out.
+fn run(root: &Path, program: &Path, backend: &str) -> Outcome {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wind-lang"))
+        .current_dir(root)
+        .arg("--backend")
+        .arg(backend)
+        .arg("--file")
+        .arg(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let input = fs::read(root.join(program).with_extension("stdin")).unwrap_or_default();
+
+    // Programs that never read their input may exit before taking all of it.
+    let _ = child.stdin.take().unwrap().write_all(&input);
+
+    let output = child.wait_with_output().unwrap();
+
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        exit_code: output.status.code().unwrap_or(-1),
+    }
+}
+
+fn expected_outcome(program: &Path) -> Outcome {
+    let source = fs::read_to_string(program).unwrap();
+    let exit_code = source
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("// exit:"))
+        .map(|code| code.trim().parse().expect("invalid exit code"))
+        .unwrap_or(0);
+
+    Outcome {
+        stdout: fs::read_to_string(program.with_extension("stdout")).unwrap_or_default(),
+        stderr: fs::read_to_string(program.with_extension("stderr")).unwrap_or_default(),
+        exit_code,
+    }
+}
+
+fn bless_program(root: &Path, program: &Path) {
+    let actual = run(root, program, BACKENDS[0]);
+    let program = root.join(program);
+
+    fs::write(program.with_extension("stdout"), &actual.stdout).unwrap();
+
+    if actual.stderr.is_empty() {
+        let _ = fs::remove_file(program.with_extension("stderr"));
+    } else {
+        fs::write(program.with_extension("stderr"), &actual.stderr).unwrap();
+    }
+}
+
+fn describe_failure(program: &Path, backend: &str, expected: &Outcome, actual: &Outcome) -> String {
+    let mut description = format!("{} ({} backend):", program.display(), backend);
+
+    if expected.exit_code != actual.exit_code {
+        description += &format!(
+            "\n  exit code: expected {}, got {}",
+            expected.exit_code, actual.exit_code
+        );
+    }
+
+    for (stream, expected, actual) in [
+        ("stdout", &expected.stdout, &actual.stdout),
+        ("stderr", &expected.stderr, &actual.stderr),
+    ] {
+        if expected != actual {
+            description += &format!(
+                "\n  {}: expected\n{}\n  got\n{}",
+                stream,
+                indent(expected),
+                indent(actual)
+            );
+        }
+    }
+
+    description
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    | {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}